@@ -0,0 +1,461 @@
+use crate::SimulationTime;
+
+/// Responses observed once per entity, e.g. the waiting time of every client
+#[derive(Debug, Clone, Default)]
+pub struct TallyStat {
+    count: usize,
+    mean: f64,
+    /// sum of the squared deviations from the mean (Welford)
+    squares: f64,
+    min: f64,
+    max: f64,
+}
+
+impl TallyStat {
+    pub fn observe(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squares += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// `None` without observations
+    pub fn mean(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.mean as f32)
+    }
+
+    /// Sample variance, `None` with less than two observations
+    pub fn variance(&self) -> Option<f32> {
+        (self.count > 1).then(|| (self.squares / (self.count - 1) as f64) as f32)
+    }
+
+    pub fn min(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.min as f32)
+    }
+
+    pub fn max(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.max as f32)
+    }
+}
+
+/// Level which changes at event times, e.g. the count of busy tables.
+/// Every level is weighted by the time it was held.
+#[derive(Debug, Clone)]
+pub struct TimeWeightedStat {
+    level: f64,
+    start: SimulationTime,
+    /// time of the last update
+    last: SimulationTime,
+    area: f64,
+    /// integral of the squared level
+    square_area: f64,
+    min: f64,
+    max: f64,
+    /// time spent at every integer level, fractional levels are rounded down
+    time_at_level: Vec<f64>,
+}
+
+impl TimeWeightedStat {
+    pub fn new(time: SimulationTime, level: f64) -> Self {
+        Self {
+            level,
+            start: time,
+            last: time,
+            area: 0.0,
+            square_area: 0.0,
+            min: level,
+            max: level,
+            time_at_level: Vec::new(),
+        }
+    }
+
+    /// The level has been held until `time` and changes to `level` from now on
+    pub fn update(&mut self, time: SimulationTime, level: f64) {
+        self.advance(time);
+
+        self.level = level;
+        self.min = self.min.min(level);
+        self.max = self.max.max(level);
+    }
+
+    /// The current level has been held until `time`
+    pub fn advance(&mut self, time: SimulationTime) {
+        let duration = time - self.last;
+        if duration <= 0.0 {
+            return;
+        }
+
+        self.area += self.level * duration;
+        self.square_area += self.level * self.level * duration;
+
+        let index = self.level.max(0.0) as usize;
+        if self.time_at_level.len() <= index {
+            self.time_at_level.resize(index + 1, 0.0);
+        }
+        self.time_at_level[index] += duration;
+
+        self.last = time;
+    }
+
+    /// Forgets the collected area, the current level is kept
+    pub fn reset(&mut self) {
+        *self = Self::new(self.last, self.level);
+    }
+
+    /// Integral of the level over the observed time
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    pub fn duration(&self) -> SimulationTime {
+        self.last - self.start
+    }
+
+    /// The current level before any time has passed
+    pub fn mean(&self) -> f32 {
+        if self.duration() > 0.0 {
+            (self.area / self.duration()) as f32
+        } else {
+            self.level as f32
+        }
+    }
+
+    pub fn variance(&self) -> f32 {
+        if self.duration() > 0.0 {
+            let mean = self.area / self.duration();
+            (self.square_area / self.duration() - mean * mean).max(0.0) as f32
+        } else {
+            0.0
+        }
+    }
+
+    pub fn min(&self) -> f32 {
+        self.min as f32
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max as f32
+    }
+
+    /// Share of the observed time spent at every integer level,
+    /// all of it at the current level before any time has passed
+    pub fn time_shares(&self) -> Vec<f32> {
+        if self.duration() > 0.0 {
+            return self
+                .time_at_level
+                .iter()
+                .map(|time| (time / self.duration()) as f32)
+                .collect();
+        }
+
+        let mut shares = vec![0.0; self.level.max(0.0) as usize + 1];
+        shares[self.level.max(0.0) as usize] = 1.0;
+        shares
+    }
+}
+
+/// Streaming estimate of one quantile with the P² algorithm (Jain & Chlamtac),
+/// five markers are kept instead of the observations
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    /// the first five observations, then the marker heights
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(p: f64) -> Self {
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let h = &mut self.heights;
+        let cell = if value < h[0] {
+            h[0] = value;
+            0
+        } else if value >= h[4] {
+            h[4] = value;
+            3
+        } else {
+            (1..5).find(|&i| value < h[i]).unwrap() - 1
+        };
+
+        for position in self.positions[cell + 1..].iter_mut() {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let n = &self.positions;
+            let offset = self.desired[i] - n[i];
+            if (offset >= 1.0 && n[i + 1] - n[i] > 1.0)
+                || (offset <= -1.0 && n[i - 1] - n[i] < -1.0)
+            {
+                let step = offset.signum();
+                let parabolic = self.parabolic(i, step);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, step)
+                    };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    /// `None` without observations, the exact quantile of the first five
+    pub fn value(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..=4 => {
+                let mut first = self.heights[..self.count].to_vec();
+                first.sort_by(f64::total_cmp);
+                Some(first[(self.p * (self.count - 1) as f64).round() as usize])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+
+        q[i] + step / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        let j = if step > 0.0 { i + 1 } else { i - 1 };
+
+        q[i] + step * (q[j] - q[i]) / (n[j] - n[i])
+    }
+}
+
+/// Service level of an observation-based response: its quantiles
+/// and the share of observations within the target
+#[derive(Debug, Clone)]
+pub struct QuantileStat {
+    quantiles: [P2Quantile; 4],
+    target: f64,
+    within: usize,
+    count: usize,
+}
+
+impl QuantileStat {
+    /// Probabilities of the estimated quantiles
+    pub const PROBABILITIES: [f64; 4] = [0.5, 0.9, 0.95, 0.99];
+
+    pub fn new(target: f64) -> Self {
+        Self {
+            quantiles: Self::PROBABILITIES.map(P2Quantile::new),
+            target,
+            within: 0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for quantile in self.quantiles.iter_mut() {
+            quantile.observe(value);
+        }
+
+        self.count += 1;
+        if value <= self.target {
+            self.within += 1;
+        }
+    }
+
+    /// In the order of `PROBABILITIES`
+    pub fn quantiles(&self) -> [Option<f32>; 4] {
+        self.quantiles
+            .each_ref()
+            .map(|quantile| quantile.value().map(|value| value as f32))
+    }
+
+    /// `None` without observations
+    pub fn share_within(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.within as f32 / self.count as f32)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn time_weighted_step_function() {
+        // 2 on [0, 3), 5 on [3, 4), 1 on [4, 10)
+        let mut stat = TimeWeightedStat::new(0.0, 2.0);
+        stat.update(3.0, 5.0);
+        stat.update(4.0, 1.0);
+        stat.advance(10.0);
+
+        assert_close(stat.area(), 17.0, 1e-9);
+        assert_close(stat.duration(), 10.0, 1e-9);
+        assert_close(stat.mean() as f64, 1.7, 1e-6);
+        // E[x²] = (4 * 3 + 25 * 1 + 1 * 6) / 10
+        assert_close(stat.variance() as f64, 4.3 - 1.7 * 1.7, 1e-5);
+        assert_eq!((stat.min(), stat.max()), (1.0, 5.0));
+
+        let shares = stat.time_shares();
+        for (level, share) in [(0, 0.0), (1, 0.6), (2, 0.3), (5, 0.1)] {
+            assert_close(shares[level] as f64, share, 1e-6);
+        }
+    }
+
+    #[test]
+    fn time_weighted_without_elapsed_time() {
+        let mut stat = TimeWeightedStat::new(5.0, 3.0);
+        assert_eq!((stat.mean(), stat.variance()), (3.0, 0.0));
+        assert_eq!(stat.time_shares(), vec![0.0, 0.0, 0.0, 1.0]);
+
+        stat.update(7.0, 1.0);
+        stat.reset();
+        assert_eq!(stat.duration(), 0.0);
+        assert_eq!(stat.mean(), 1.0);
+    }
+
+    #[test]
+    fn welford_matches_two_pass_variance() {
+        // a large offset loses the precision of the naive sum of squares
+        let values = [4.0, 7.0, 13.0, 16.0, 9.5, 11.25].map(|value| value + 1e9);
+
+        let mut stat = TallyStat::default();
+        for value in values {
+            stat.observe(value);
+        }
+
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let two_pass = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / (values.len() - 1) as f64;
+
+        assert_eq!(stat.count(), values.len());
+        assert_close(stat.variance().unwrap() as f64, two_pass, 1e-3);
+        assert_eq!(stat.min(), Some((4.0 + 1e9) as f32));
+        assert_eq!(stat.max(), Some((16.0 + 1e9) as f32));
+    }
+
+    #[test]
+    fn tally_without_enough_observations() {
+        let mut stat = TallyStat::default();
+        assert_eq!(
+            (stat.mean(), stat.variance(), stat.min()),
+            (None, None, None)
+        );
+
+        stat.observe(2.0);
+        assert_eq!((stat.mean(), stat.variance()), (Some(2.0), None));
+    }
+
+    /// Exponential with the unit mean, drawn from a fixed seed
+    fn sample(count: usize) -> Vec<f64> {
+        let mut rng = crate::rng::stream_rng(7, 0);
+        (0..count)
+            .map(|_| -(1.0 - rng.random::<f64>()).ln())
+            .collect()
+    }
+
+    fn exact_quantile(sorted: &[f64], p: f64) -> f64 {
+        sorted[((p * sorted.len() as f64).ceil() as usize).max(1) - 1]
+    }
+
+    #[test]
+    fn p2_follows_exact_quantiles() {
+        let values = sample(20_000);
+        let mut sorted = values.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        for p in [0.5, 0.95] {
+            let mut quantile = P2Quantile::new(p);
+            for &value in values.iter() {
+                quantile.observe(value);
+            }
+
+            let exact = exact_quantile(&sorted, p);
+            assert_close(quantile.value().unwrap(), exact, 0.02 * exact);
+        }
+    }
+
+    #[test]
+    fn p2_with_fewer_than_five_observations() {
+        let mut median = P2Quantile::new(0.5);
+        let mut high = P2Quantile::new(0.95);
+        assert_eq!(median.value(), None);
+
+        for value in [3.0, 1.0, 2.0] {
+            median.observe(value);
+            high.observe(value);
+        }
+        assert_eq!(median.value(), Some(2.0));
+        assert_eq!(high.value(), Some(3.0));
+    }
+
+    #[test]
+    fn service_level_share_within_target() {
+        let mut stat = QuantileStat::new(2.0);
+        assert_eq!(stat.quantiles(), [None; 4]);
+        assert_eq!(stat.share_within(), None);
+
+        for value in [1.0, 2.0, 3.0, 4.0] {
+            stat.observe(value);
+        }
+        assert_eq!(stat.share_within(), Some(0.5));
+        assert_eq!(stat.quantiles()[0], Some(3.0));
+
+        stat.reset();
+        assert_eq!(stat.share_within(), None);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    egui_charts::histogram::get_histogram, statistic::StatsConfig, EstimationConfig, Log,
-    Simulation, Stats,
+    batch::Batch, debugger::Debugger, egui_charts::histogram::get_histogram, floor_plan::FloorPlan,
+    statistic::StatsConfig, Distribution, EnsembleLog, EstimationConfig, Responses, Stats,
 };
 use egui::Color32;
 use egui_plot::{Bar, BarChart, Legend, Line, Plot, PlotBounds, PlotPoints, Points};
@@ -10,6 +10,18 @@ pub struct EguiApp {
     is_running: bool,
     data: Vec<f64>,
     free_workers_over_time: Vec<f64>,
+    /// replications of the histogram, kept going while running
+    data_batch: Option<Batch>,
+    /// replications of the free workers over time
+    log_batch: Option<Batch>,
+    /// first sub-stream of the next batch, so every batch draws new replications
+    next_stream: u64,
+    /// logs received so far
+    free_workers_log: EnsembleLog,
+    debugger: Debugger,
+    show_debugger: bool,
+    floor_plan: FloorPlan,
+    show_floor_plan: bool,
 }
 
 impl EguiApp {
@@ -26,10 +38,30 @@ impl EguiApp {
             is_running: false,
             data: Vec::new(),
             free_workers_over_time: Vec::new(),
+            data_batch: None,
+            log_batch: None,
+            next_stream: 0,
+            free_workers_log: EnsembleLog::empty(),
+            debugger: Debugger::default(),
+            show_debugger: false,
+            floor_plan: FloorPlan::default(),
+            show_floor_plan: false,
         }
     }
 }
 
+fn distribution_sliders(ui: &mut egui::Ui, distribution: &mut Distribution, name: &str) {
+    ui.horizontal(|ui| match distribution {
+        Distribution::Uniform { start, end } => {
+            ui.add(egui::Slider::new(start, 0.0..=100.0));
+            ui.add(egui::Slider::new(end, 0.0..=100.0).text(name));
+        }
+        other => {
+            ui.label(format!("{name}: {other:?}"));
+        }
+    });
+}
+
 fn plot_histogram(ui: &mut egui::Ui, data: &[f64], name: &str) {
     let histogram = get_histogram(data);
     let bars: Vec<_> = histogram
@@ -131,12 +163,25 @@ impl eframe::App for EguiApp {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
                 });
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut self.show_debugger, "Debugger");
+                    ui.checkbox(&mut self.show_floor_plan, "Floor plan");
+                });
                 ui.add_space(16.0);
 
                 egui::widgets::global_theme_preference_buttons(ui);
             });
         });
 
+        egui::Window::new("Debugger")
+            .open(&mut self.show_debugger)
+            .vscroll(true)
+            .show(ctx, |ui| self.debugger.ui(ui, &self.config.simulation));
+
+        egui::Window::new("Floor plan")
+            .open(&mut self.show_floor_plan)
+            .show(ctx, |ui| self.floor_plan.ui(ui, &self.config.simulation));
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(
                 egui::Layout::left_to_right(egui::Align::Min).with_cross_justify(true),
@@ -148,22 +193,10 @@ impl eframe::App for EguiApp {
                         }
 
                         if ui.button("Graph of free workers").clicked {
-                            let mut sim = Simulation::with_config(self.config.simulation.clone());
-                            let mut avg_log = Log::empty();
-                            let amount_of_runs = 1000;
-
-                            for _ in 0..amount_of_runs {
-                                let (results, log) = sim.run();
-                                sim.reset_metrics();
-                                avg_log.add_mut(log);
-                            }
-
-                            avg_log.norm_mut(amount_of_runs);
-                            self.free_workers_over_time = avg_log
-                                .iter()
-                                .map(|e| e.1.average_free_workers as f64)
-                                .collect::<Vec<_>>();
+                            self.free_workers_log = EnsembleLog::empty();
+                            self.log_batch = Some(self.spawn_batch(1000));
                         }
+                        batch_progress(ui, &mut self.log_batch);
 
                         let simulation_config = &mut self.config.simulation;
                         ui.add(
@@ -178,50 +211,27 @@ impl eframe::App for EguiApp {
                             egui::Slider::new(&mut simulation_config.tables, 1..=100)
                                 .text("Tables count"),
                         );
-                        ui.horizontal(|ui| {
-                            ui.add(egui::Slider::new(
-                                &mut simulation_config.dancing_time.start,
-                                1..=100,
-                            ));
-                            ui.add(
-                                egui::Slider::new(&mut simulation_config.dancing_time.end, 1..=100)
-                                    .text("Dancing time range"),
-                            );
-                        });
-                        ui.horizontal(|ui| {
-                            ui.add(egui::Slider::new(
-                                &mut simulation_config.production_time.start,
-                                1..=100,
-                            ));
-                            ui.add(
-                                egui::Slider::new(
-                                    &mut simulation_config.production_time.end,
-                                    1..=100,
-                                )
-                                .text("Production time"),
-                            );
-                        });
-                        ui.horizontal(|ui| {
-                            ui.add(egui::Slider::new(
-                                &mut simulation_config.consumption_time.start,
-                                1..=100,
-                            ));
-                            ui.add(
-                                egui::Slider::new(
-                                    &mut simulation_config.consumption_time.end,
-                                    1..=100,
-                                )
-                                .text("Consumption time"),
-                            );
-                        });
-
-                        if ui.button("Click to gen 10").clicked {
-                            let mut sim = Simulation::with_config(self.config.simulation.clone());
-                            for _ in 0..10 {
-                                let (results, _) = sim.run();
-                                self.data.push(results.average_free_workers as f64);
-                                sim.reset_metrics();
-                            }
+                        distribution_sliders(
+                            ui,
+                            &mut simulation_config.dancing_time,
+                            "Dancing time range",
+                        );
+                        distribution_sliders(
+                            ui,
+                            &mut simulation_config.production_time,
+                            "Production time",
+                        );
+                        distribution_sliders(
+                            ui,
+                            &mut simulation_config.consumption_time,
+                            "Consumption time",
+                        );
+
+                        if ui.button("Click to gen 10").clicked && self.data_batch.is_none() {
+                            self.data_batch = Some(self.spawn_batch(10));
+                        }
+                        if batch_progress(ui, &mut self.data_batch) {
+                            self.is_running = false;
                         }
                         if ui.button("reset simulation").clicked {
                             self.data.clear();
@@ -592,11 +602,77 @@ impl eframe::App for EguiApp {
             );
         });
 
-        if self.is_running {
-            let mut sim = Simulation::with_config(self.config.simulation.clone());
-            let (results, _) = sim.run();
-            self.data.push(results.average_free_workers as f64);
-            sim.reset_metrics();
+        self.poll_batches();
+    }
+}
+
+impl EguiApp {
+    /// Spawns replications on the sub-streams no batch has used yet
+    fn spawn_batch(&mut self, total: usize) -> Batch {
+        let batch = Batch::spawn(self.config.simulation.clone(), self.next_stream, total);
+        self.next_stream += total as u64;
+
+        batch
+    }
+
+    /// Takes the replications finished by the background batches
+    fn poll_batches(&mut self) {
+        if let Some(batch) = &mut self.data_batch {
+            for (results, _) in batch.poll() {
+                if let Some(free_workers) = results.get("average_free_workers") {
+                    self.data.push(free_workers as f64);
+                }
+            }
+            if batch.is_finished() {
+                self.data_batch = None;
+            }
+        }
+        // running keeps the histogram growing batch after batch
+        if self.is_running && self.data_batch.is_none() {
+            self.data_batch = Some(self.spawn_batch(10));
+        }
+
+        if let Some(batch) = &mut self.log_batch {
+            let runs = batch.poll();
+            if !runs.is_empty() {
+                for (_, log) in runs {
+                    self.free_workers_log.add(&log);
+                }
+
+                self.free_workers_over_time = self
+                    .free_workers_log
+                    .column("average_free_workers")
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|stat| stat.mean().unwrap_or_default() as f64)
+                    .collect();
+            }
+            if batch.is_finished() {
+                self.log_batch = None;
+            }
         }
     }
 }
+
+/// Progress of the batch with a button to cancel it, returns whether it was cancelled
+fn batch_progress(ui: &mut egui::Ui, batch: &mut Option<Batch>) -> bool {
+    let Some(running) = batch else {
+        return false;
+    };
+
+    let mut cancelled = false;
+    ui.horizontal(|ui| {
+        ui.add(egui::ProgressBar::new(running.progress()).show_percentage());
+        if running.failed() > 0 {
+            ui.colored_label(Color32::RED, format!("{} failed", running.failed()));
+        }
+        cancelled = ui.button("Cancel").clicked();
+    });
+
+    // dropping the batch cancels it
+    if cancelled {
+        *batch = None;
+    }
+
+    cancelled
+}
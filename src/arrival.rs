@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{Distribution, SimulationTick, SimulationTime};
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "snake_case",
+    try_from = "RawArrivalProcess"
+)]
+pub enum ArrivalProcess {
+    /// one Bernoulli trial with `client_ratio` on every tick
+    #[default]
+    Bernoulli,
+    /// non-homogeneous Poisson process sampled by thinning, every arrival brings `batch` groups
+    Poisson {
+        profile: RateProfile,
+        #[serde(default = "single_group")]
+        batch: Distribution,
+    },
+    /// arrival times recorded in a real cafe, stored as whitespace separated numbers.
+    /// Every run replays the times from its start, the ones after the end of a run are left out.
+    Trace {
+        path: String,
+        /// sorted
+        #[serde(skip)]
+        times: Arc<Vec<SimulationTime>>,
+    },
+}
+
+/// `ArrivalProcess` as it is written in the config, before the trace is read
+#[derive(serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum RawArrivalProcess {
+    Bernoulli,
+    Poisson {
+        profile: RateProfile,
+        #[serde(default = "single_group")]
+        batch: Distribution,
+    },
+    Trace {
+        path: String,
+    },
+}
+
+impl TryFrom<RawArrivalProcess> for ArrivalProcess {
+    type Error = String;
+
+    fn try_from(raw: RawArrivalProcess) -> Result<Self, Self::Error> {
+        Ok(match raw {
+            RawArrivalProcess::Bernoulli => Self::Bernoulli,
+            RawArrivalProcess::Poisson { profile, batch } => Self::Poisson { profile, batch },
+            RawArrivalProcess::Trace { path } => {
+                let times = Arc::new(load_trace(&path)?);
+                Self::Trace { path, times }
+            }
+        })
+    }
+}
+
+fn single_group() -> Distribution {
+    Distribution::constant(1.0)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// the rate of a point holds until the next point
+    #[default]
+    Constant,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct RatePoint {
+    pub time: SimulationTime,
+    /// expected arrivals per time unit
+    pub rate: f64,
+}
+
+/// Time-of-day profile of the arrival rate
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RateProfile {
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// points ordered by time
+    pub points: Vec<RatePoint>,
+    /// the profile repeats with this period (e.g. a day), the last rate holds otherwise
+    pub period: Option<SimulationTime>,
+}
+
+impl RateProfile {
+    pub fn rate(&self, time: SimulationTime) -> f64 {
+        let time = match self.period {
+            Some(period) if period > 0.0 => time.rem_euclid(period),
+            _ => time,
+        };
+
+        let next = self.points.partition_point(|p| p.time <= time);
+        if next == 0 {
+            return self.points.first().map_or(0.0, |p| p.rate);
+        }
+
+        let left = self.points[next - 1];
+        match (self.interpolation, self.points.get(next)) {
+            (Interpolation::Linear, Some(right)) => {
+                let share = (time - left.time) / (right.time - left.time);
+                left.rate + (right.rate - left.rate) * share
+            }
+            _ => left.rate,
+        }
+    }
+
+    pub fn max_rate(&self) -> f64 {
+        self.points.iter().map(|p| p.rate).fold(0.0, f64::max)
+    }
+}
+
+/// State of the arrival process during a simulation
+pub struct Arrivals {
+    process: ArrivalProcess,
+    client_ratio: f64,
+    run_time: SimulationTime,
+    trace_position: usize,
+    /// start of the run the trace is replayed in
+    trace_offset: SimulationTime,
+}
+
+impl Arrivals {
+    pub fn new(process: ArrivalProcess, client_ratio: f64, run_time: SimulationTick) -> Self {
+        Self {
+            process,
+            client_ratio,
+            run_time: run_time as SimulationTime,
+            trace_position: 0,
+            trace_offset: 0.0,
+        }
+    }
+
+    /// Time of the next arrival after `time` (from the very start if `None`),
+    /// `None` if no more clients will come
+    pub fn next<R: Rng + ?Sized>(
+        &mut self,
+        time: Option<SimulationTime>,
+        rng: &mut R,
+    ) -> Option<SimulationTime> {
+        match &self.process {
+            ArrivalProcess::Bernoulli => {
+                let ratio = self.client_ratio;
+                if ratio <= 0.0 {
+                    return None;
+                }
+
+                let from = time.map_or(0, |t| t as SimulationTick + 1);
+                // count of failed trials before the success is geometric
+                let skipped = if ratio >= 1.0 {
+                    0
+                } else {
+                    let u = 1.0 - rng.random::<f64>();
+                    (u.ln() / (1.0 - ratio).ln()).floor() as SimulationTick
+                };
+
+                Some(from.saturating_add(skipped) as SimulationTime)
+            }
+            ArrivalProcess::Poisson { profile, .. } => {
+                let max_rate = profile.max_rate();
+                if max_rate <= 0.0 {
+                    return None;
+                }
+
+                let last = *profile.points.last()?;
+                let mut candidate = time.unwrap_or(0.0);
+                loop {
+                    candidate -= (1.0 - rng.random::<f64>()).ln() / max_rate;
+                    if profile.period.is_none() && candidate >= last.time && last.rate <= 0.0 {
+                        return None;
+                    }
+                    if rng.random::<f64>() * max_rate <= profile.rate(candidate) {
+                        return Some(candidate);
+                    }
+                }
+            }
+            ArrivalProcess::Trace { times, .. } => {
+                // the runs of a continuous experiment follow each other, each one replays the trace
+                let times = &times[..times.partition_point(|&t| t < self.run_time)];
+                if times.is_empty() {
+                    return None;
+                }
+                let from = time.unwrap_or(f64::NEG_INFINITY);
+
+                loop {
+                    let Some(&candidate) = times.get(self.trace_position) else {
+                        self.trace_position = 0;
+                        self.trace_offset += self.run_time;
+                        continue;
+                    };
+                    self.trace_position += 1;
+
+                    let candidate = candidate + self.trace_offset;
+                    if candidate >= from {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count of groups arriving together
+    pub fn batch_size<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
+        match &self.process {
+            ArrivalProcess::Poisson { batch, .. } => batch.sample(rng).round().max(1.0) as u32,
+            _ => 1,
+        }
+    }
+}
+
+fn load_trace(path: &str) -> Result<Vec<SimulationTime>, String> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read arrivals trace {path}: {e}"))?;
+    let mut times = raw
+        .split_whitespace()
+        .map(|v| match v.parse::<SimulationTime>() {
+            Ok(time) if time.is_finite() && time >= 0.0 => Ok(time),
+            _ => Err(format!("Bad arrival time {v} in arrivals trace {path}")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    times.sort_by(|a, b| a.total_cmp(b));
+
+    Ok(times)
+}
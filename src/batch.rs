@@ -0,0 +1,101 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{rng, Log, Results, Simulation, SimulationConfig};
+
+/// Replications run on the rayon pool, their results are streamed back as they finish.
+/// Dropping the batch cancels it.
+pub struct Batch {
+    /// `None` for a replication which panicked
+    receiver: Receiver<Option<(Results, Log)>>,
+    cancelled: Arc<AtomicBool>,
+    total: usize,
+    /// finished replications, the failed ones included
+    done: usize,
+    failed: usize,
+}
+
+impl Batch {
+    /// Runs the sub-streams `first_stream..first_stream + total` of the master seed
+    pub fn spawn(config: SimulationConfig, first_stream: u64, total: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let seed = rng::resolve_seed(config.seed);
+
+        let flag = cancelled.clone();
+        rayon::spawn(move || {
+            (0..total)
+                .into_par_iter()
+                .for_each_with(sender, |sender, i| {
+                    if flag.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    let stream = first_stream + i as u64;
+                    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                        Simulation::with_stream(config.clone(), seed, stream).run()
+                    }));
+                    if run.is_err() {
+                        log::error!("Replication {stream} of seed {seed} failed");
+                    }
+                    // nobody listens after the batch is dropped
+                    let _ = sender.send(run.ok());
+                });
+        });
+
+        Self {
+            receiver,
+            cancelled,
+            total,
+            done: 0,
+            failed: 0,
+        }
+    }
+
+    /// Replications finished since the last call, the failed ones are only counted
+    pub fn poll(&mut self) -> Vec<(Results, Log)> {
+        let runs = self.receiver.try_iter().collect::<Vec<_>>();
+        let received = runs.len();
+        self.done += received;
+
+        let succeeded = runs.into_iter().flatten().collect::<Vec<_>>();
+        self.failed += received - succeeded.len();
+        succeeded
+    }
+
+    /// Share of the finished runs, an empty batch is done
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+
+        self.done as f32 / self.total as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done >= self.total
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Replications which have not started yet are skipped
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
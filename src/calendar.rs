@@ -0,0 +1,88 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::SimulationTime;
+
+struct Entry<E> {
+    time: SimulationTime,
+    event: E,
+    /// keeps events scheduled for the same time in FIFO order
+    sequence: u64,
+}
+
+impl<E> PartialEq for Entry<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<E> Eq for Entry<E> {}
+
+impl<E> PartialOrd for Entry<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E> Ord for Entry<E> {
+    // reversed, so the max-heap pops the earliest event first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .total_cmp(&self.time)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+/// Future event list ordered by the event time
+pub struct Calendar<E> {
+    queue: BinaryHeap<Entry<E>>,
+    sequence: u64,
+}
+
+impl<E> Calendar<E> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            queue: BinaryHeap::with_capacity(capacity),
+            sequence: 0,
+        }
+    }
+
+    pub fn schedule(&mut self, time: SimulationTime, event: E) {
+        self.queue.push(Entry {
+            time,
+            event,
+            sequence: self.sequence,
+        });
+        self.sequence += 1;
+    }
+
+    /// The first `limit` pending events in the order they are going to happen
+    pub fn pending(&self, limit: usize) -> Vec<(SimulationTime, &E)> {
+        let mut entries = self.queue.iter().collect::<Vec<_>>();
+        // the earliest entry is the greatest one, only the first ones are sorted
+        if entries.len() > limit {
+            entries.select_nth_unstable_by(limit, |a, b| b.cmp(a));
+            entries.truncate(limit);
+        }
+        entries.sort_by(|a, b| b.cmp(a));
+
+        entries
+            .into_iter()
+            .map(|entry| (entry.time, &entry.event))
+            .collect()
+    }
+
+    /// Pending events in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (SimulationTime, &E)> {
+        self.queue.iter().map(|entry| (entry.time, &entry.event))
+    }
+
+    /// Pops the earliest event with its time if it happens before `end_time`
+    pub fn pop_before(&mut self, end_time: SimulationTime) -> Option<(SimulationTime, E)> {
+        if self.queue.peek()?.time < end_time {
+            self.queue.pop().map(|entry| (entry.time, entry.event))
+        } else {
+            None
+        }
+    }
+}
@@ -1,24 +1,25 @@
 use plotters::prelude::*;
 
+/// Bars without a value are left out, the others keep their places
 pub struct Bar<'a> {
-    pub y_data: Vec<f64>,
+    pub y_data: Vec<Option<f64>>,
     pub title: &'a str,
 }
 
 impl<'a> Bar<'a> {
-    pub fn from_y_data(title: &'a str, data: Vec<f32>) -> Self {
-        let y_data = data.into_iter().map(|v| v as f64).collect();
+    pub fn from_y_data(title: &'a str, data: Vec<Option<f32>>) -> Self {
+        let y_data = data.into_iter().map(|v| v.map(f64::from)).collect();
 
         Self { y_data, title }
     }
 
     pub fn save(&'a self, file_name: &str) -> std::io::Result<()> {
-        let max_y = self
+        let max_y = *self
             .y_data
             .iter()
+            .flatten()
             .max_by(|a, b| a.total_cmp(b))
-            .unwrap()
-            .clone();
+            .unwrap();
 
         let count = (self.y_data.len() * 3 + (self.y_data.len() - 1) * 3) as f64;
         let chart_name = format!("{file_name}.png");
@@ -37,13 +38,13 @@ impl<'a> Bar<'a> {
         chart.configure_mesh().draw().unwrap();
 
         chart
-            .draw_series(self.y_data.iter().enumerate().map(|(index, v)| {
+            .draw_series(self.y_data.iter().enumerate().filter_map(|(index, v)| {
                 let x0 = (index * 3 * 2) as f64;
                 let x1 = x0 + 3.0;
 
-                let y0 = *v;
+                let y0 = (*v)?;
                 let y1 = 0.0;
-                Rectangle::new([(x0, y0), (x1, y1)], BLUE.filled())
+                Some(Rectangle::new([(x0, y0), (x1, y1)], BLUE.filled()))
             }))
             .unwrap();
 
@@ -8,7 +8,6 @@ pub struct Histogram<'a> {
     pub y_data: Vec<f64>,
     //the count of batch
     pub bins: usize,
-    pub use_stats: bool,
     pub title: &'a str,
 }
 
@@ -20,7 +19,6 @@ impl<'a> Histogram<'a> {
 
         Self {
             y_data,
-            use_stats: false,
             bins: find_best_bins(y_count),
             title,
         }
@@ -33,12 +31,6 @@ impl<'a> Histogram<'a> {
         self
     }
 
-    pub fn use_stats(&'a mut self, stats: bool) -> &'a Self {
-        self.use_stats = stats;
-
-        self
-    }
-
     pub fn save(&'a self, file_name: &str, config: &StatsConfig) -> std::io::Result<()> {
         let plot_samples = prepare_plot_samples(&self.y_data, self.bins);
         let total_count = self.y_data.len();
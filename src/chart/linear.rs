@@ -10,6 +10,14 @@ pub struct Linear<'a> {
 
     pub use_approximation: bool,
     pub config: Option<&'a StatsConfig>,
+    /// shaded ranges around the data, drawn from the widest one
+    pub bands: Vec<Band>,
+}
+
+pub struct Band {
+    pub label: String,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
 }
 
 impl<'a> Linear<'a> {
@@ -25,9 +33,23 @@ impl<'a> Linear<'a> {
             y_data,
             use_approximation: false,
             config: None,
+            bands: Vec::new(),
         }
     }
 
+    /// Range around the data at every x, e.g. a confidence interval
+    pub fn add_band(&mut self, label: &str, lower: Vec<f32>, upper: Vec<f32>) -> &mut Self {
+        assert!(lower.len() == self.x_data.len() && upper.len() == self.x_data.len());
+
+        self.bands.push(Band {
+            label: label.to_string(),
+            lower: lower.into_iter().map(|v| v as f64).collect(),
+            upper: upper.into_iter().map(|v| v as f64).collect(),
+        });
+
+        self
+    }
+
     pub fn use_approximation(&mut self, use_it: bool) -> &mut Self {
         self.use_approximation = use_it;
 
@@ -46,8 +68,18 @@ impl<'a> Linear<'a> {
         let min_x = self.x_data.iter().fold(f64::MAX, |a, b| a.min(*b));
         let max_x = self.x_data.iter().fold(f64::MIN, |a, b| a.max(*b));
 
-        let min_y = self.y_data.iter().fold(f64::MAX, |a, b| a.min(*b));
-        let max_y = self.y_data.iter().fold(f64::MIN, |a, b| a.max(*b));
+        let min_y = self
+            .bands
+            .iter()
+            .flat_map(|band| band.lower.iter())
+            .chain(self.y_data.iter())
+            .fold(f64::MAX, |a, b| a.min(*b));
+        let max_y = self
+            .bands
+            .iter()
+            .flat_map(|band| band.upper.iter())
+            .chain(self.y_data.iter())
+            .fold(f64::MIN, |a, b| a.max(*b));
 
         let root = BitMapBackend::new(&chart_name, (1024, 1024)).into_drawing_area();
 
@@ -63,6 +95,24 @@ impl<'a> Linear<'a> {
 
         chart.configure_mesh().draw().unwrap();
 
+        for (index, band) in self.bands.iter().enumerate() {
+            let outline = self
+                .x_data
+                .iter()
+                .zip(band.upper.iter())
+                .chain(self.x_data.iter().zip(band.lower.iter()).rev())
+                .map(|(x, y)| (*x, *y))
+                .collect::<Vec<_>>();
+
+            chart
+                .draw_series(std::iter::once(Polygon::new(
+                    outline,
+                    BLUE.mix(0.1 * (index + 1) as f64).filled(),
+                )))
+                .unwrap()
+                .label(&band.label);
+        }
+
         chart
             .draw_series(
                 LineSeries::new(
@@ -1,3 +1,5 @@
+use crate::response::ResponseInfo;
+
 mod bar;
 mod histogram;
 mod hyperplane;
@@ -7,3 +9,17 @@ pub use bar::Bar;
 pub use histogram::Histogram;
 pub use hyperplane::HyperPlane;
 pub use linear::Linear;
+
+/// File name and title of the chart of a response.
+/// The responses charted before they could be selected keep their names from `names`.
+pub fn name_of(
+    info: &ResponseInfo,
+    names: &[(&str, &str, &str)],
+    title: impl FnOnce() -> String,
+) -> (String, String) {
+    names
+        .iter()
+        .find(|(key, ..)| *key == info.key)
+        .map(|(_, file, title)| (file.to_string(), title.to_string()))
+        .unwrap_or_else(|| (info.key.clone(), title()))
+}
@@ -0,0 +1,126 @@
+use crate::{hall::Seating, Distribution, EntityId, SimulationTime};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientClass {
+    #[default]
+    WalkIn,
+    Reservation,
+    Vip,
+}
+
+impl ClientClass {
+    pub const ALL: [ClientClass; 3] = [Self::WalkIn, Self::Reservation, Self::Vip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::WalkIn => "walk_in",
+            Self::Reservation => "reservation",
+            Self::Vip => "vip",
+        }
+    }
+}
+
+/// Behaviour of a client class, unset delays fall back to the common ones
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ClassBehaviour {
+    /// workers and the kitchen serve higher priority first
+    #[serde(default)]
+    pub priority: u32,
+    pub patience: Option<Distribution>,
+    pub dancing_time: Option<Distribution>,
+    pub consumption_time: Option<Distribution>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ClientClasses {
+    /// the probability that an arriving group without reservation is VIP
+    #[serde(default)]
+    pub vip_share: f64,
+    #[serde(default)]
+    pub walk_in: ClassBehaviour,
+    #[serde(default)]
+    pub reservation: ClassBehaviour,
+    #[serde(default)]
+    pub vip: ClassBehaviour,
+}
+
+impl ClientClasses {
+    pub fn get(&self, class: ClientClass) -> &ClassBehaviour {
+        match class {
+            ClientClass::WalkIn => &self.walk_in,
+            ClientClass::Reservation => &self.reservation,
+            ClientClass::Vip => &self.vip,
+        }
+    }
+}
+
+/// Where the group is in its visit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientStage {
+    /// in the line at the entrance
+    Entrance,
+    WaitingForWorker,
+    WaitingForFood,
+    Eating,
+    WaitingForBill,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepartureReason {
+    /// left immediately, no free table on arrival
+    Balked,
+    /// left without being served by a worker
+    Reneged,
+    Served,
+}
+
+/// Lifecycle record of one visit, timestamps of the first order are kept
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Client {
+    pub id: EntityId,
+    pub class: ClientClass,
+    pub group_size: u32,
+    /// tables the group is seated at
+    pub seating: Seating,
+    pub stage: ClientStage,
+
+    pub arrival: SimulationTime,
+    pub seated: Option<SimulationTime>,
+    pub order_taken: Option<SimulationTime>,
+    pub food_ready: Option<SimulationTime>,
+    pub consumption_start: Option<SimulationTime>,
+    /// times the client asked for one more order
+    pub reorders: Vec<SimulationTime>,
+    /// revenue of all orders taken from the group
+    pub bill: f64,
+    pub departure: Option<SimulationTime>,
+    pub departure_reason: Option<DepartureReason>,
+}
+
+impl Client {
+    pub fn new(id: EntityId, class: ClientClass, group_size: u32, arrival: SimulationTime) -> Self {
+        Self {
+            id,
+            class,
+            group_size,
+            seating: Seating::new(),
+            stage: ClientStage::Entrance,
+            arrival,
+            seated: None,
+            order_taken: None,
+            food_ready: None,
+            consumption_start: None,
+            reorders: Vec::new(),
+            bill: 0.0,
+            departure: None,
+            departure_reason: None,
+        }
+    }
+
+    pub fn sojourn_time(&self) -> Option<SimulationTime> {
+        self.departure.map(|departure| departure - self.arrival)
+    }
+}
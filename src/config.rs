@@ -1,24 +1,263 @@
-use std::{
-    fmt::{Display, Formatter},
-    ops::Range,
-};
+use std::fmt::{Display, Formatter};
 
 use crate::{
-    scenario::ScenarioConfig, statistic::StatsConfig, ExperimentConfig,
+    arrival::ArrivalProcess,
+    client::ClientClasses,
+    economics::EconomicsConfig,
+    hall::{EntranceConfig, SeatingPolicy, TableGroup},
+    kitchen::KitchenConfig,
+    menu::Menu,
+    reservation::ReservationBook,
+    scenario::ScenarioConfig,
+    staff::{DispatchPolicy, StaffChange},
+    statistic::StatsConfig,
+    trace::TraceConfig,
+    Distribution, ExperimentConfig, ModelConfig, SimulationTick, SimulationTime,
 };
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SimulationConfig {
+    /// workers on duty at the start
     pub workers: u32,
+    /// changes of the count of workers on duty during the run
+    #[serde(default)]
+    pub staffing: Vec<StaffChange>,
     pub tables: u32,
+    /// seats at every table when `table_layout` is not set
+    #[serde(default = "default_seats")]
+    pub seats: u32,
+    /// tables of different capacities, replaces `tables` and `seats`.
+    /// A `tables` scenario scales the counts of the groups to the total instead.
+    #[serde(default)]
+    pub table_layout: Vec<TableGroup>,
+    #[serde(default)]
+    pub seating_policy: SeatingPolicy,
+    /// groups which found no free table wait here, the line is disabled by default
+    #[serde(default)]
+    pub entrance: EntranceConfig,
     pub max_time: u32,
 
     /// the probability that client will appear on every simulation tick
     pub client_ratio: f64,
-    pub production_time: Range<u32>,
-    pub dancing_time: Range<u32>,
-    pub consumption_time: Range<u32>,
+    /// arrival process, `client_ratio` is used by the default Bernoulli one
+    #[serde(default)]
+    pub arrivals: ArrivalProcess,
+    /// count of visitors in an arriving group (rounded up)
+    #[serde(default = "default_group_size")]
+    pub group_size: Distribution,
+    /// production time of every order when the menu is empty
+    pub production_time: Distribution,
+    pub dancing_time: Distribution,
+    pub consumption_time: Distribution,
+    /// time a worker spends bringing the food from the kitchen
+    #[serde(default = "default_service_time")]
+    pub delivery_time: Distribution,
+    /// time a worker spends taking the payment and clearing the table
+    #[serde(default = "default_service_time")]
+    pub payment_time: Distribution,
+    #[serde(default)]
+    pub dispatch_policy: DispatchPolicy,
     pub use_logs: bool,
+    #[serde(default)]
+    pub client_behaviour: ClientBehaviour,
+    /// the share of clients served within every target is a response
+    #[serde(default)]
+    pub service_targets: ServiceTargets,
+    /// priorities and behaviour of walk-in, reservation and VIP clients
+    #[serde(default)]
+    pub classes: ClientClasses,
+    #[serde(default)]
+    pub reservations: ReservationBook,
+    #[serde(default)]
+    pub kitchen: KitchenConfig,
+    #[serde(default)]
+    pub menu: Menu,
+    /// prices and costs, everything is free by default
+    #[serde(default)]
+    pub economics: EconomicsConfig,
+    /// structured trace of the events, for single runs
+    #[serde(default)]
+    pub trace: TraceConfig,
+    /// initial capacity of the pending events queue
+    #[serde(default = "default_events_capacity")]
+    pub events_capacity: usize,
+    /// master seed, every replication gets its own sub-stream derived from it
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ClientBehaviour {
+    /// how long a seated client waits for a worker before leaving
+    pub patience: Distribution,
+    /// the probability that client will order once more after consumption
+    pub reorder_probability: f64,
+    /// how long a client waits for a worker to make a repeat order
+    pub reorder_patience: Distribution,
+}
+
+impl Default for ClientBehaviour {
+    fn default() -> Self {
+        Self {
+            patience: Distribution::uniform(5.0, 10.0),
+            reorder_probability: 0.2,
+            reorder_patience: Distribution::uniform(1.0, 3.0),
+        }
+    }
+}
+
+/// Service-level targets, e.g. 95% of clients greeted within 3 time units
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ServiceTargets {
+    /// from sitting down or asking for a re-order to the worker coming
+    pub worker_waiting_time: SimulationTime,
+    /// from the order being taken to the food being delivered
+    pub food_waiting_time: SimulationTime,
+    /// from the arrival to the departure of a served client
+    pub sojourn_time: SimulationTime,
+}
+
+impl Default for ServiceTargets {
+    fn default() -> Self {
+        Self {
+            worker_waiting_time: 3.0,
+            food_waiting_time: 15.0,
+            sojourn_time: 60.0,
+        }
+    }
+}
+
+fn default_events_capacity() -> usize {
+    150
+}
+
+fn default_service_time() -> Distribution {
+    Distribution::constant(0.0)
+}
+
+fn default_seats() -> u32 {
+    4
+}
+
+fn default_group_size() -> Distribution {
+    Distribution::constant(1.0)
+}
+
+impl SimulationConfig {
+    pub fn table_capacities(&self) -> Vec<u32> {
+        if self.table_layout.is_empty() {
+            return vec![self.seats; self.tables as usize];
+        }
+
+        self.table_layout
+            .iter()
+            .flat_map(|group| std::iter::repeat_n(group.seats, group.count as usize))
+            .collect()
+    }
+
+    /// Sets the total count of tables in the layout, the shares of the groups are kept
+    /// (largest remainder rounding)
+    fn scale_table_layout(&mut self, tables: u32) {
+        let total = self
+            .table_layout
+            .iter()
+            .map(|group| group.count)
+            .sum::<u32>();
+        if total == 0 {
+            return;
+        }
+
+        let shares = self
+            .table_layout
+            .iter()
+            .map(|group| group.count as f64 * tables as f64 / total as f64)
+            .collect::<Vec<_>>();
+        for (group, share) in self.table_layout.iter_mut().zip(shares.iter()) {
+            group.count = share.floor() as u32;
+        }
+
+        let mut by_remainder = (0..shares.len()).collect::<Vec<_>>();
+        by_remainder.sort_by(|&a, &b| (shares[b] % 1.0).total_cmp(&(shares[a] % 1.0)));
+        let placed = self
+            .table_layout
+            .iter()
+            .map(|group| group.count)
+            .sum::<u32>();
+        for &index in by_remainder
+            .iter()
+            .take(tables.saturating_sub(placed) as usize)
+        {
+            self.table_layout[index].count += 1;
+        }
+    }
+}
+
+impl ModelConfig for SimulationConfig {
+    type Parameter = ParameterKind;
+
+    fn run_time(&self) -> SimulationTick {
+        self.max_time
+    }
+
+    fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    fn use_logs(&self) -> bool {
+        self.use_logs
+    }
+
+    fn set_use_logs(&mut self, use_logs: bool) {
+        self.use_logs = use_logs;
+    }
+
+    fn set_parameter(&mut self, parameter: ParameterKind, v: u32) {
+        match parameter {
+            ParameterKind::Workers => {
+                self.workers = v;
+            }
+            ParameterKind::Tables => {
+                self.tables = v;
+                self.scale_table_layout(v);
+            }
+            ParameterKind::Clients => self.client_ratio = v as f64 / 100.0,
+            ParameterKind::Dancing => {
+                self.dancing_time = Distribution::uniform(1.0, v as f64);
+            }
+            ParameterKind::Production => {
+                self.production_time = Distribution::uniform(1.0, v as f64);
+            }
+            ParameterKind::Cooks => {
+                self.kitchen.cooks = Some(v);
+            }
+            ParameterKind::Patience => {
+                self.client_behaviour.patience = Distribution::uniform(1.0, v as f64);
+            }
+            ParameterKind::ReorderProbability => {
+                self.client_behaviour.reorder_probability = v as f64 / 100.0;
+            }
+        }
+    }
+}
+
+/// Variables of the cafe the scenarios change
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterKind {
+    Workers,
+    Tables,
+    Clients,
+    Dancing,
+    Production,
+    Patience,
+    Cooks,
+    /// re-order probability in percents
+    ReorderProbability,
+}
+
+impl Display for ParameterKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
 
 impl Display for SimulationConfig {
@@ -31,7 +270,7 @@ impl Display for SimulationConfig {
 pub struct EstimationConfig {
     pub simulation: SimulationConfig,
     pub stats: StatsConfig,
-    pub scenario: Option<ScenarioConfig>,
+    pub scenario: Option<ScenarioConfig<ParameterKind>>,
     pub experiment: ExperimentConfig,
 }
 
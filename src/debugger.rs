@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+
+use egui::{CollapsingHeader, Color32, DragValue, Grid, ScrollArea, Slider};
+
+use crate::{
+    trace::{self, Levels, TraceEvent},
+    Event, Model, Simulation, SimulationConfig, SimulationTime,
+};
+
+/// Handled events kept for the view
+const HISTORY_LEN: usize = 100;
+/// Rows of the pending events and of the clients shown at once
+const SHOWN_ROWS: usize = 50;
+
+/// Steps a single run of the cafe and replays recorded traces
+pub struct Debugger {
+    simulation: Option<Simulation>,
+    /// handled events of the live run, the latest last
+    history: VecDeque<(SimulationTime, Event)>,
+    running: bool,
+    /// events handled per frame while running
+    events_per_frame: usize,
+    target_time: SimulationTime,
+
+    trace_path: String,
+    replay: Vec<TraceEvent>,
+    /// index of the shown event of the replay
+    position: usize,
+    error: Option<String>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            simulation: None,
+            history: VecDeque::new(),
+            running: false,
+            events_per_frame: 1,
+            target_time: 0.0,
+            trace_path: "trace.csv".to_string(),
+            replay: Vec::new(),
+            position: 0,
+            error: None,
+        }
+    }
+}
+
+impl Debugger {
+    pub fn ui(&mut self, ui: &mut egui::Ui, config: &SimulationConfig) {
+        CollapsingHeader::new("Live run")
+            .default_open(true)
+            .show(ui, |ui| self.live_ui(ui, config));
+
+        CollapsingHeader::new("Trace replay").show(ui, |ui| self.replay_ui(ui));
+    }
+
+    fn live_ui(&mut self, ui: &mut egui::Ui, config: &SimulationConfig) {
+        ui.horizontal(|ui| {
+            if ui.button("Restart").clicked() {
+                self.simulation = Some(Simulation::with_config(config.clone()));
+                self.history.clear();
+                self.running = false;
+            }
+            if ui.button("Step event").clicked() {
+                self.step(config);
+            }
+            if ui.button("Step tick").clicked() {
+                let next_tick = self.now().floor() + 1.0;
+                self.run_until(config, next_tick);
+            }
+
+            let label = if self.running { "Pause" } else { "Resume" };
+            if ui.button(label).clicked() {
+                self.running = !self.running;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut self.target_time).range(0.0..=f64::MAX));
+            if ui.button("Run to time").clicked() {
+                self.run_until(config, self.target_time);
+            }
+            ui.add(Slider::new(&mut self.events_per_frame, 1..=100).text("events per frame"));
+        });
+
+        if self.running {
+            for _ in 0..self.events_per_frame {
+                if !self.step(config) {
+                    self.running = false;
+                    break;
+                }
+            }
+        }
+
+        let Some(simulation) = &mut self.simulation else {
+            ui.label("Restart or step to begin a run");
+            return;
+        };
+
+        ui.label(format!(
+            "Time: {:.3} of {:.3}",
+            simulation.now(),
+            simulation.run_end()
+        ));
+        levels_grid(ui, "live_levels", None, &simulation.model().levels());
+
+        ui.separator();
+        ui.label("Pending events");
+        ScrollArea::vertical()
+            .id_salt("pending_events")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                Grid::new("pending_events_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let calendar = simulation.model_mut().calendar();
+                        for (time, event) in calendar.pending(SHOWN_ROWS) {
+                            ui.label(format!("{time:.3}"));
+                            ui.label(event.to_string());
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        ui.separator();
+        ui.label("Clients");
+        ScrollArea::vertical()
+            .id_salt("clients")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                Grid::new("clients_grid").striped(true).show(ui, |ui| {
+                    for name in ["client", "class", "group", "stage", "arrival", "tables"] {
+                        ui.strong(name);
+                    }
+                    ui.end_row();
+
+                    for client in simulation.model().clients().into_iter().take(SHOWN_ROWS) {
+                        ui.label(format!("#{}", client.id));
+                        ui.label(client.class.name());
+                        ui.label(client.group_size.to_string());
+                        ui.label(format!("{:?}", client.stage));
+                        ui.label(format!("{:.3}", client.arrival));
+                        ui.label(
+                            client
+                                .seating
+                                .iter()
+                                .map(|(table, _)| table.to_string())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        );
+                        ui.end_row();
+                    }
+                });
+            });
+
+        ui.separator();
+        ui.label("Handled events");
+        ScrollArea::vertical()
+            .id_salt("handled_events")
+            .max_height(150.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for (time, event) in self.history.iter() {
+                    ui.label(format!("{time:.3}: {event}"));
+                }
+            });
+    }
+
+    fn replay_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.trace_path);
+            if ui.button("Load").clicked() {
+                match trace::load(&self.trace_path) {
+                    Ok(events) => {
+                        self.replay = events;
+                        self.position = 0;
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error.to_string()),
+                }
+            }
+        });
+        ui.label("CSV or JSON Lines traces can be replayed");
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+        if self.replay.is_empty() {
+            return;
+        }
+
+        let last = self.replay.len() - 1;
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                self.position = self.position.saturating_sub(1);
+            }
+            ui.add(Slider::new(&mut self.position, 0..=last));
+            if ui.button(">").clicked() {
+                self.position = (self.position + 1).min(last);
+            }
+        });
+
+        let event = self.replay[self.position];
+        ui.label(format!("{:.3}: {}", event.time, event.event()));
+        levels_grid(ui, "replay_levels", Some(&event.before), &event.after);
+
+        ui.separator();
+        let from = self.position.saturating_sub(SHOWN_ROWS / 2);
+        let to = (from + SHOWN_ROWS).min(self.replay.len());
+        ScrollArea::vertical()
+            .id_salt("replay_events")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for index in from..to {
+                    let event = &self.replay[index];
+                    let text = format!("{:.3}: {}", event.time, event.event());
+                    if ui.selectable_label(index == self.position, text).clicked() {
+                        self.position = index;
+                    }
+                }
+            });
+    }
+
+    fn now(&self) -> SimulationTime {
+        self.simulation
+            .as_ref()
+            .map_or(0.0, |simulation| simulation.now())
+    }
+
+    /// Handles the next event, `false` if there is none before the end of the run
+    fn step(&mut self, config: &SimulationConfig) -> bool {
+        let simulation = self
+            .simulation
+            .get_or_insert_with(|| Simulation::with_config(config.clone()));
+
+        match simulation.step_before(simulation.run_end()) {
+            Some(step) => {
+                self.record(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn run_until(&mut self, config: &SimulationConfig, time: SimulationTime) {
+        let simulation = self
+            .simulation
+            .get_or_insert_with(|| Simulation::with_config(config.clone()));
+
+        for step in simulation.run_until(time.min(simulation.run_end())) {
+            self.record(step);
+        }
+    }
+
+    fn record(&mut self, step: (SimulationTime, Event)) {
+        self.history.push_back(step);
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+}
+
+/// Levels of the resources, with the ones before the event if they are known
+fn levels_grid(ui: &mut egui::Ui, id: &str, before: Option<&Levels>, after: &Levels) {
+    Grid::new(id).striped(true).show(ui, |ui| {
+        for (index, (name, value)) in after.named().enumerate() {
+            ui.label(name);
+            if let Some(before) = before {
+                let (_, previous) = before.named().nth(index).unwrap();
+                ui.label(previous.to_string());
+            }
+            ui.label(value.to_string());
+            ui.end_row();
+        }
+    });
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use rand::Rng;
+use statrs::distribution::{ContinuousCDF, Gamma, LogNormal, Normal};
+
+/// Random delay of the model, selected in `config.toml` by the `kind` tag.
+/// Parameters are checked and empirical samples are loaded when the config is read.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", try_from = "RawDistribution")]
+pub enum Distribution {
+    Uniform {
+        start: f64,
+        end: f64,
+    },
+    Exponential {
+        mean: f64,
+    },
+    /// normal distribution truncated to `min..max` (non-negative by default)
+    Normal {
+        mean: f64,
+        std_dev: f64,
+        min: f64,
+        max: f64,
+    },
+    LogNormal {
+        mu: f64,
+        sigma: f64,
+    },
+    Gamma {
+        shape: f64,
+        scale: f64,
+    },
+    Triangular {
+        min: f64,
+        mode: f64,
+        max: f64,
+    },
+    /// sum of `k` exponential phases with the total mean `mean`
+    Erlang {
+        k: u32,
+        mean: f64,
+    },
+    Constant {
+        value: f64,
+    },
+    /// resamples observed values, stored in the file as whitespace separated numbers
+    Empirical {
+        path: String,
+        #[serde(skip)]
+        samples: Arc<Vec<f64>>,
+    },
+}
+
+/// `Distribution` as it is written in the config, before the checks
+#[derive(serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum RawDistribution {
+    Uniform {
+        start: f64,
+        end: f64,
+    },
+    Exponential {
+        mean: f64,
+    },
+    Normal {
+        mean: f64,
+        std_dev: f64,
+        #[serde(default)]
+        min: f64,
+        #[serde(default = "infinity")]
+        max: f64,
+    },
+    LogNormal {
+        mu: f64,
+        sigma: f64,
+    },
+    Gamma {
+        shape: f64,
+        scale: f64,
+    },
+    Triangular {
+        min: f64,
+        mode: f64,
+        max: f64,
+    },
+    Erlang {
+        k: u32,
+        mean: f64,
+    },
+    Constant {
+        value: f64,
+    },
+    Empirical {
+        path: String,
+    },
+}
+
+impl TryFrom<RawDistribution> for Distribution {
+    type Error = String;
+
+    fn try_from(raw: RawDistribution) -> Result<Self, Self::Error> {
+        let distribution = match raw {
+            RawDistribution::Uniform { start, end } => {
+                ensure(start <= end, "uniform start is greater than its end")?;
+                Self::Uniform { start, end }
+            }
+            RawDistribution::Exponential { mean } => {
+                ensure(mean > 0.0, "exponential mean must be positive")?;
+                Self::Exponential { mean }
+            }
+            RawDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                ensure(std_dev > 0.0, "normal std_dev must be positive")?;
+                ensure(min < max, "normal truncation bounds are empty")?;
+                Self::Normal {
+                    mean,
+                    std_dev,
+                    min,
+                    max,
+                }
+            }
+            RawDistribution::LogNormal { mu, sigma } => {
+                ensure(sigma > 0.0, "log-normal sigma must be positive")?;
+                Self::LogNormal { mu, sigma }
+            }
+            RawDistribution::Gamma { shape, scale } => {
+                ensure(shape > 0.0, "gamma shape must be positive")?;
+                ensure(scale > 0.0, "gamma scale must be positive")?;
+                Self::Gamma { shape, scale }
+            }
+            RawDistribution::Triangular { min, mode, max } => {
+                ensure(min < max, "triangular min must be less than its max")?;
+                ensure(
+                    (min..=max).contains(&mode),
+                    "triangular mode is out of min..max",
+                )?;
+                Self::Triangular { min, mode, max }
+            }
+            RawDistribution::Erlang { k, mean } => {
+                ensure(k > 0, "erlang needs at least one phase")?;
+                ensure(mean > 0.0, "erlang mean must be positive")?;
+                Self::Erlang { k, mean }
+            }
+            RawDistribution::Constant { value } => Self::Constant { value },
+            RawDistribution::Empirical { path } => {
+                let samples = Arc::new(load_samples(&path)?);
+                Self::Empirical { path, samples }
+            }
+        };
+
+        Ok(distribution)
+    }
+}
+
+fn ensure(condition: bool, message: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message.to_string())
+    }
+}
+
+fn infinity() -> f64 {
+    f64::INFINITY
+}
+
+impl Distribution {
+    pub fn uniform(start: f64, end: f64) -> Self {
+        Self::Uniform { start, end }
+    }
+
+    pub fn constant(value: f64) -> Self {
+        Self::Constant { value }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Self::Uniform { start, end } => start + (end - start) * rng.random::<f64>(),
+            Self::Exponential { mean } => exponential(rng, *mean),
+            Self::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                let normal = Normal::new(*mean, *std_dev).unwrap();
+                let (low, high) = (normal.cdf(*min), normal.cdf(*max));
+                if (high - low).abs() < f64::EPSILON {
+                    log::warn!("Truncation bounds of normal distribution are hardly reachable");
+                    return mean.clamp(*min, *max);
+                }
+
+                normal
+                    .inverse_cdf(low + (high - low) * open_unit(rng))
+                    .clamp(*min, *max)
+            }
+            Self::LogNormal { mu, sigma } => LogNormal::new(*mu, *sigma)
+                .unwrap()
+                .inverse_cdf(open_unit(rng)),
+            Self::Gamma { shape, scale } => Gamma::new(*shape, 1.0 / scale)
+                .unwrap()
+                .inverse_cdf(open_unit(rng)),
+            Self::Triangular { min, mode, max } => {
+                let u = rng.random::<f64>();
+                let split = (mode - min) / (max - min);
+
+                if u < split {
+                    min + (u * (max - min) * (mode - min)).sqrt()
+                } else {
+                    max - ((1.0 - u) * (max - min) * (max - mode)).sqrt()
+                }
+            }
+            Self::Erlang { k, mean } => {
+                let phase_mean = mean / *k as f64;
+                (0..*k).map(|_| exponential(rng, phase_mean)).sum()
+            }
+            Self::Constant { value } => *value,
+            Self::Empirical { samples, .. } => samples[rng.gen_range(0..samples.len())],
+        }
+    }
+}
+
+fn load_samples(path: &str) -> Result<Vec<f64>, String> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read empirical distribution {path}: {e}"))?;
+    let samples = raw
+        .split_whitespace()
+        .map(|v| match v.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(format!("Bad sample {v} in empirical distribution {path}")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    ensure(
+        !samples.is_empty(),
+        &format!("Empirical distribution {path} is empty"),
+    )?;
+    Ok(samples)
+}
+
+fn exponential<R: Rng + ?Sized>(rng: &mut R, mean: f64) -> f64 {
+    -mean * (1.0 - rng.random::<f64>()).ln()
+}
+
+/// Uniform on `(0, 1)`, so quantile functions stay finite.
+/// The samplers of statrs take the `Rng` of an older rand,
+/// so its distributions are sampled through their quantile functions.
+fn open_unit<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    loop {
+        let u = rng.random::<f64>();
+        if u > 0.0 {
+            return u;
+        }
+    }
+}
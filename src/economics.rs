@@ -0,0 +1,32 @@
+use crate::Distribution;
+
+/// Prices and costs of the cafe, money is in any single currency unit
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct EconomicsConfig {
+    /// revenue of the first order of a group
+    pub order_revenue: Distribution,
+    /// revenue of every repeat order
+    pub reorder_revenue: Distribution,
+    /// cost of a worker on duty per hour
+    pub worker_hourly_cost: f64,
+    /// rent and upkeep of a table per hour, paid whether it is busy or not
+    pub table_hourly_cost: f64,
+    /// lost goodwill for every client who left without being served
+    pub lost_client_penalty: f64,
+    /// simulation time units in an hour
+    pub time_units_per_hour: f64,
+}
+
+impl Default for EconomicsConfig {
+    fn default() -> Self {
+        Self {
+            order_revenue: Distribution::constant(0.0),
+            reorder_revenue: Distribution::constant(0.0),
+            worker_hourly_cost: 0.0,
+            table_hourly_cost: 0.0,
+            lost_client_penalty: 0.0,
+            time_units_per_hour: 60.0,
+        }
+    }
+}
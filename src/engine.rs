@@ -0,0 +1,198 @@
+use std::{fmt::Display, sync::Arc};
+
+use crate::{
+    response::Registry,
+    rng::{self, SimRng},
+    Calendar, Log,
+};
+
+/// Point of the fixed grid the logs are sampled on
+pub type SimulationTick = u32;
+pub type SimulationTime = f64;
+
+/// Configuration of a model, the parts the engine and the scenarios need to know
+pub trait ModelConfig: Clone + Send + Sync {
+    /// Variable of the model a scenario changes
+    type Parameter: Copy + Display + Send + Sync;
+
+    /// Length of one run
+    fn run_time(&self) -> SimulationTick;
+    /// Master seed, a fresh one is drawn if not set
+    fn seed(&self) -> Option<u64>;
+    /// Whether responses are logged on every tick
+    fn use_logs(&self) -> bool;
+    fn set_use_logs(&mut self, use_logs: bool);
+    fn set_parameter(&mut self, parameter: Self::Parameter, value: u32);
+}
+
+/// Responses of one run, also the average of several runs
+pub trait Responses: Clone + Default + Send {
+    fn add_mut(&mut self, other: Self);
+    fn norm_mut(&mut self, count: usize);
+    /// Declared responses, empty until a run is added to the default
+    fn registry(&self) -> &Registry;
+    /// `None` if none of the runs had data for the response
+    fn get(&self, key: &str) -> Option<f32>;
+    /// Count of the added runs without data for the response
+    fn empty_runs(&self, key: &str) -> usize;
+}
+
+/// Discrete-event model, which owns its state and the calendar of pending events
+pub trait Model {
+    type Config: ModelConfig;
+    type Event;
+    type Responses: Responses;
+
+    /// Initial state of the model drawing from `rng`
+    fn new(config: Self::Config, rng: SimRng) -> Self;
+    /// Schedules the first events, called before the first run
+    fn start(&mut self);
+    fn calendar(&mut self) -> &mut Calendar<Self::Event>;
+    fn handle(&mut self, time: SimulationTime, event: Self::Event);
+    /// Observes the state at `time`, on the integer grid and at the end of a run
+    fn observe(&mut self, time: SimulationTime);
+    /// Declared responses, the same for every run of a config
+    fn registry(&self) -> &Registry;
+    fn responses(&self) -> Self::Responses;
+    /// Only the `selected` responses, see `Registry::subset`; the others have no data
+    fn selected_responses(&self, selected: &Arc<Registry>) -> Self::Responses;
+    /// Forgets the collected statistics, but keeps the state (continuous experiments)
+    fn reset_metrics(&mut self);
+}
+
+/// Runs a model jumping from event to event
+pub struct Engine<M: Model> {
+    model: M,
+    run_time: SimulationTick,
+    use_logs: bool,
+    /// logged responses, all the declared ones if `None`
+    logged: Option<Arc<Registry>>,
+    /// end of the last run, `None` before the first one
+    world_time: Option<SimulationTime>,
+}
+
+impl<M: Model> Engine<M> {
+    pub fn with_config(config: M::Config) -> Self {
+        let seed = rng::resolve_seed(config.seed());
+        Self::with_stream(config, seed, 0)
+    }
+
+    /// Creates the engine of one replication, which draws from its own sub-stream of `seed`
+    pub fn with_stream(config: M::Config, seed: u64, stream: u64) -> Self {
+        Self {
+            run_time: config.run_time(),
+            use_logs: config.use_logs(),
+            logged: None,
+            model: M::new(config, rng::stream_rng(seed, stream)),
+            world_time: None,
+        }
+    }
+
+    /// Logs only the `keys` responses, all of them if empty
+    pub fn log_only(&mut self, keys: &[String]) {
+        self.logged = (!keys.is_empty()).then(|| Arc::new(self.model.registry().subset(keys)));
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    /// Time of the last handled event or the end of the last run
+    pub fn now(&self) -> SimulationTime {
+        self.world_time.unwrap_or(0.0)
+    }
+
+    /// End of the first run, stepping through a run should stop there
+    pub fn run_end(&self) -> SimulationTime {
+        self.run_time as SimulationTime
+    }
+
+    /// Handles the next event if it happens before `end_time`, for stepping through a run
+    pub fn step_before(&mut self, end_time: SimulationTime) -> Option<(SimulationTime, M::Event)>
+    where
+        M::Event: Clone,
+    {
+        self.start_once();
+
+        let (time, event) = self.model.calendar().pop_before(end_time)?;
+        self.model.handle(time, event.clone());
+        self.world_time = Some(time);
+
+        Some((time, event))
+    }
+
+    /// Handles every event before `time` and moves the clock to it, returns the handled events
+    pub fn run_until(&mut self, time: SimulationTime) -> Vec<(SimulationTime, M::Event)>
+    where
+        M::Event: Clone,
+    {
+        let mut handled = Vec::new();
+        while let Some(step) = self.step_before(time) {
+            handled.push(step);
+        }
+        self.world_time = Some(self.now().max(time));
+
+        handled
+    }
+
+    /// Advances the model by the run time, the next run continues from where this one stopped.
+    /// Logs are sampled on the integer grid relative to the start of the run.
+    pub fn run(&mut self) -> (M::Responses, Log) {
+        let mut log = Log::of_selected(self.logged.clone());
+
+        let start_time = self.start_once();
+        let end_time = start_time + self.run_time as SimulationTime;
+        let mut tick: SimulationTick = 0;
+
+        while let Some((time, event)) = self.model.calendar().pop_before(end_time) {
+            while start_time + (tick as SimulationTime) < time {
+                self.sample_tick(start_time, tick, &mut log);
+                tick += 1;
+            }
+
+            self.model.handle(time, event);
+        }
+
+        while tick < self.run_time {
+            self.sample_tick(start_time, tick, &mut log);
+            tick += 1;
+        }
+        self.model.observe(end_time);
+
+        self.world_time = Some(end_time);
+
+        (self.model.responses(), log)
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.model.reset_metrics();
+    }
+
+    /// Schedules the first events before anything is handled, returns the current time
+    fn start_once(&mut self) -> SimulationTime {
+        match self.world_time {
+            Some(time) => time,
+            None => {
+                self.model.start();
+                self.world_time = Some(0.0);
+                0.0
+            }
+        }
+    }
+
+    fn sample_tick(&mut self, start_time: SimulationTime, tick: SimulationTick, log: &mut Log) {
+        self.model.observe(start_time + tick as SimulationTime);
+
+        if self.use_logs {
+            let entry = match &self.logged {
+                Some(selected) => self.model.selected_responses(selected),
+                None => self.model.responses(),
+            };
+            log.append(tick, &entry);
+        }
+    }
+}
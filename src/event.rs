@@ -1,9 +1,125 @@
-pub enum Event {
-    Enter(u32),
-
-    WaitingForWorker(u32, u32, bool),
-    // LeavePlace,
-    ConsumeFood(u32),
-    WorkerWalkingDance(u32),
-    WaitingForFood(u32), //random val
+use std::fmt::{Display, Formatter};
+
+/// Identifier of the client the event belongs to
+pub type EntityId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Arrival,
+    /// client stops waiting in the line at the entrance and leaves
+    EntranceReneged,
+    /// client stops waiting for a worker and leaves the table
+    PatienceExpired,
+    /// worker finished "dancing" and passes the order to the kitchen
+    OrderTaken,
+    /// a dish is cooked, the payload is the kitchen ticket of the dish
+    DishReady,
+    /// worker brought the food, the client starts consuming
+    FoodDelivered,
+    ConsumptionFinished,
+    /// worker took the payment and cleared the table
+    PaymentTaken,
+    /// count of workers on duty changes, the payload is the index of the change in the schedule
+    ShiftChange,
+    /// tables are held for a reservation, the payload is the index of the reservation
+    ReservationHold,
+    /// held tables are released if the group has not come,
+    /// the payload is the index of the reservation
+    ReservationExpired,
+    /// the group with a reservation comes to the cafe
+    ReservationArrival,
+}
+
+impl EventKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Arrival => "arrival",
+            Self::EntranceReneged => "entrance_reneged",
+            Self::PatienceExpired => "patience_expired",
+            Self::OrderTaken => "order_taken",
+            Self::DishReady => "dish_ready",
+            Self::FoodDelivered => "food_delivered",
+            Self::ConsumptionFinished => "consumption_finished",
+            Self::PaymentTaken => "payment_taken",
+            Self::ShiftChange => "shift_change",
+            Self::ReservationHold => "reservation_hold",
+            Self::ReservationExpired => "reservation_expired",
+            Self::ReservationArrival => "reservation_arrival",
+        }
+    }
+
+    /// What the payload of the event is, `None` if it has no payload
+    pub fn payload_name(self) -> Option<&'static str> {
+        match self {
+            Self::DishReady => Some("ticket"),
+            Self::ShiftChange => Some("shift"),
+            Self::ReservationHold | Self::ReservationExpired => Some("reservation"),
+            _ => None,
+        }
+    }
+}
+
+/// Event of the cafe model, its time is kept by the calendar
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    /// client the event belongs to, `None` for the events of the cafe itself
+    pub entity: Option<EntityId>,
+    pub kind: EventKind,
+    /// kitchen ticket or index in the config, see `EventKind`
+    pub payload: Option<u32>,
+}
+
+impl Event {
+    pub fn new(entity: EntityId, kind: EventKind) -> Self {
+        Self {
+            entity: Some(entity),
+            kind,
+            payload: None,
+        }
+    }
+
+    /// Event of the cafe itself, e.g. a shift change
+    pub fn of_cafe(kind: EventKind, payload: u32) -> Self {
+        Self {
+            entity: None,
+            kind,
+            payload: Some(payload),
+        }
+    }
+
+    /// Event of the client with a payload, e.g. the kitchen ticket of a dish
+    pub fn with_payload(entity: EntityId, kind: EventKind, payload: u32) -> Self {
+        Self {
+            entity: Some(entity),
+            kind,
+            payload: Some(payload),
+        }
+    }
+
+    /// Panics on the events of the cafe
+    pub fn client(&self) -> EntityId {
+        self.entity.expect("Event of the cafe has no client")
+    }
+
+    /// Panics on the events without a payload
+    pub fn payload(&self) -> u32 {
+        self.payload.expect("Event has no payload")
+    }
+}
+
+/// e.g. `dish_ready of #4 (ticket 17)` or `shift_change (shift 2)`
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind.name())?;
+        if let Some(entity) = self.entity {
+            write!(f, " of #{entity}")?;
+        }
+        if let Some(payload) = self.payload {
+            let name = self.kind.payload_name().unwrap_or("payload");
+            write!(f, " ({name} {payload})")?;
+        }
+
+        Ok(())
+    }
 }
@@ -1,40 +1,38 @@
+use std::collections::BTreeMap;
+
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use statrs::{
-    distribution::{ContinuousCDF, StudentsT},
-    statistics::Statistics,
-};
+use statrs::distribution::{ContinuousCDF, StudentsT};
 
 use crate::{
-    chart::Linear,
+    chart::{self, Linear},
+    history::TickStat,
+    response::ResponseInfo,
+    rng,
     statistic::{f_test, t_test, FisherTest, StudentTest},
-    EstimationConfig, Log, Results, Simulation,
+    Engine, EnsembleLog, Model, ModelConfig, Responses,
 };
 
+/// Confidence level of the bands around the mean responses over time
+const BAND_CONFIDENCE: f64 = 0.95;
+/// Runs of a parallel experiment held at once by every thread before their logs are merged
+const RUNS_PER_THREAD: usize = 4;
+
 #[derive(serde::Serialize)]
 pub struct Test {
-    pub name: &'static str,
+    pub name: String,
     pub t_test: StudentTest,
     pub f_test: FisherTest,
 }
 
 #[derive(serde::Serialize)]
-pub struct ExperimentResult {
-    pub runs: Results,
+pub struct ExperimentResult<R> {
+    pub seed: u64,
+    pub runs: R,
     pub tests: Vec<Test>,
-}
-
-#[derive(Clone, serde::Serialize)]
-pub enum Gap {
-    Absolute(usize),
-    MeanVariance(usize),
-}
-
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum BaseParameter {
-    FreeWorkers,
-    BusyTables,
-    WaitingTime,
+    /// units and kinds of the tested responses
+    pub responses: Vec<ResponseInfo>,
+    /// runs without data for the response, e.g. no client was served; they are not averaged
+    pub empty_runs: BTreeMap<String, usize>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -45,183 +43,131 @@ pub struct ExperimentConfig {
     /// use continous experiment (warmed up state)
     pub continous: bool,
     pub gap_size: usize,
-    pub parameter: BaseParameter,
+    /// keys of the responses charted over time and tested, all the declared ones if empty
+    #[serde(default)]
+    pub responses: Vec<String>,
 }
 
-pub fn run(config: EstimationConfig, base_path: &str) -> Results {
-    let mut total_results = Results::zeros();
-    let mut total_logs = Log::empty();
-    let mut results = Vec::<Results>::new();
+pub fn run<M: Model>(
+    model_config: M::Config,
+    experiment: &ExperimentConfig,
+    base_path: &str,
+) -> M::Responses
+where
+    M::Responses: serde::Serialize,
+{
+    let mut total_results = M::Responses::default();
+    let mut total_logs = EnsembleLog::empty();
+    let mut results = Vec::<M::Responses>::new();
+    let seed = rng::resolve_seed(model_config.seed());
 
-    if config.experiment.continous {
-        let mut sim = Simulation::with_config(config.simulation.clone());
-        for _ in 0..config.experiment.total {
+    if experiment.continous {
+        let mut sim = Engine::<M>::with_stream(model_config.clone(), seed, 0);
+        sim.log_only(&experiment.responses);
+        for _ in 0..experiment.total {
             sim.reset_metrics();
             let (run_result, run_log) = sim.run();
             total_results.add_mut(run_result.clone());
-            total_logs.add_mut(run_log);
+            total_logs.add(&run_log);
             results.push(run_result);
         }
     } else {
-        let tmp = (0..config.experiment.total)
-            .into_par_iter()
-            .map(|_| {
-                let mut sim = Simulation::with_config(config.simulation.clone());
-                sim.run()
-            })
-            .collect::<Vec<_>>();
+        // the logs of a chunk of runs are merged in the order of the runs before the next one,
+        // so only a chunk of them is held and the quantile estimates do not depend on the threads
+        let chunk_size = rayon::current_num_threads() * RUNS_PER_THREAD;
+        for start in (0..experiment.total).step_by(chunk_size) {
+            let end = (start + chunk_size).min(experiment.total);
+            let chunk = (start..end)
+                .into_par_iter()
+                .map(|i| {
+                    let mut sim = Engine::<M>::with_stream(model_config.clone(), seed, i as u64);
+                    sim.log_only(&experiment.responses);
+                    sim.run()
+                })
+                .collect::<Vec<_>>();
 
-        tmp.into_iter().for_each(|(run_result, run_log)| {
-            total_results.add_mut(run_result.clone());
-            total_logs.add_mut(run_log);
+            for (run_result, run_log) in chunk {
+                total_results.add_mut(run_result.clone());
+                total_logs.add(&run_log);
 
-            results.push(run_result);
-        });
+                results.push(run_result);
+            }
+        }
     }
 
-    total_results.norm_mut(config.experiment.total);
-    total_logs.norm_mut(config.experiment.total);
+    total_results.norm_mut(experiment.total);
 
-    assert!(config.experiment.total > 2, "At least 3 run must be set");
+    assert!(experiment.total > 2, "At least 3 run must be set");
 
-    let logs_entries = total_logs
-        .iter()
-        .map(|(_tick, entry)| entry.clone())
+    let responses = total_results
+        .registry()
+        .select(&experiment.responses)
+        .into_iter()
+        .cloned()
         .collect::<Vec<_>>();
 
-    let sim_duration = config.simulation.max_time as usize;
-    assert_eq!(sim_duration, logs_entries.len());
-
-    Linear::from_data(
-        "BusyTables over Time",
-        total_logs.iter().map(|(tick, _)| tick as f32).collect(),
-        total_logs
-            .iter()
-            .map(|(_, entry)| entry.average_busy_tables)
-            .collect(),
-    )
-    .save(&format!("{base_path}/BusyTables"))
-    .unwrap();
-
-    Linear::from_data(
-        "FreeWorkers Over Time",
-        total_logs.iter().map(|(tick, _)| tick as f32).collect(),
-        total_logs
-            .iter()
-            .map(|(_, entry)| entry.average_free_workers)
-            .collect(),
-    )
-    .save(&format!("{base_path}/FreeWorkers"))
-    .unwrap();
-
-    Linear::from_data(
-        "WaitingTime Over Time",
-        total_logs.iter().map(|(tick, _)| tick as f32).collect(),
-        total_logs
-            .iter()
-            .map(|(_, entry)| entry.average_worker_waiting_time)
-            .collect(),
-    )
-    .save(&format!("{base_path}/WaitingTime"))
-    .unwrap();
-
-    Linear::from_data(
-        "Dispatched Clients",
-        total_logs.iter().map(|(tick, _)| tick as f32).collect(),
-        total_logs
-            .iter()
-            .map(|(_, entry)| entry.dispatched_clients)
-            .collect(),
-    )
-    .save(&format!("{base_path}/DispatchedClients"))
-    .unwrap();
+    // without logs (`use_logs = false`) there is nothing to chart over time
+    if total_logs.tick_count() > 0 {
+        assert_eq!(model_config.run_time() as usize, total_logs.tick_count());
+        chart_over_time(&total_logs, &responses, base_path);
+    }
 
     let long_data = results
         .iter()
-        .skip(config.experiment.gap_size)
+        .skip(experiment.gap_size)
         .cloned()
         .collect::<Vec<_>>();
 
     let short_data = results
         .iter()
-        .skip(config.experiment.gap_size)
-        .take(config.experiment.min_total)
+        .skip(experiment.gap_size)
+        .take(experiment.min_total)
         .cloned()
         .collect::<Vec<_>>();
 
+    // runs without data are left out of the tests
+    let tests = responses
+        .iter()
+        .filter_map(|info| {
+            let long = long_data
+                .iter()
+                .filter_map(|r| r.get(&info.key))
+                .map(|value| value as f64)
+                .collect::<Vec<_>>();
+            let short = short_data
+                .iter()
+                .filter_map(|r| r.get(&info.key))
+                .map(|value| value as f64)
+                .collect::<Vec<_>>();
+            if short.len() < 2 {
+                log::warn!("Too few runs with data to test {}", info.key);
+                return None;
+            }
+
+            Some(Test {
+                t_test: t_test(&long, &short),
+                f_test: f_test(&long, &short),
+                name: info.key.clone(),
+            })
+        })
+        .collect();
+
+    let empty_runs = total_results
+        .registry()
+        .iter()
+        .map(|info| (info.key.clone(), total_results.empty_runs(&info.key)))
+        .filter(|&(_, count)| count > 0)
+        .collect::<BTreeMap<_, _>>();
+    for (key, count) in empty_runs.iter() {
+        log::warn!("{count} of {} runs had no data for {key}", experiment.total);
+    }
+
     let experiment_results = ExperimentResult {
+        seed,
         runs: total_results.clone(),
-        tests: vec![
-            Test {
-                name: "busy_tables",
-                t_test: t_test(
-                    &long_data
-                        .iter()
-                        .map(|r| r.average_busy_tables as f64)
-                        .collect::<Vec<_>>(),
-                    &short_data
-                        .iter()
-                        .map(|r| r.average_busy_tables as f64)
-                        .collect::<Vec<_>>(),
-                ),
-                f_test: f_test(
-                    &long_data
-                        .iter()
-                        .map(|r| r.average_busy_tables as f64)
-                        .collect::<Vec<_>>(),
-                    &short_data
-                        .iter()
-                        .map(|r| r.average_busy_tables as f64)
-                        .collect::<Vec<_>>(),
-                ),
-            },
-            Test {
-                name: "free_workers",
-                t_test: t_test(
-                    &long_data
-                        .iter()
-                        .map(|r| r.average_free_workers as f64)
-                        .collect::<Vec<_>>(),
-                    &short_data
-                        .iter()
-                        .map(|r| r.average_free_workers as f64)
-                        .collect::<Vec<_>>(),
-                ),
-                f_test: f_test(
-                    &long_data
-                        .iter()
-                        .map(|r| r.average_free_workers as f64)
-                        .collect::<Vec<_>>(),
-                    &short_data
-                        .iter()
-                        .map(|r| r.average_free_workers as f64)
-                        .collect::<Vec<_>>(),
-                ),
-            },
-            Test {
-                name: "waiting_time",
-                t_test: t_test(
-                    &long_data
-                        .iter()
-                        .map(|r| r.average_worker_waiting_time as f64)
-                        .collect::<Vec<_>>(),
-                    &short_data
-                        .iter()
-                        .map(|r| r.average_worker_waiting_time as f64)
-                        .collect::<Vec<_>>(),
-                ),
-                f_test: f_test(
-                    &long_data
-                        .iter()
-                        .map(|r| r.average_worker_waiting_time as f64)
-                        .collect::<Vec<_>>(),
-                    &short_data
-                        .iter()
-                        .map(|r| r.average_worker_waiting_time as f64)
-                        .collect::<Vec<_>>(),
-                ),
-            },
-        ],
+        tests,
+        responses,
+        empty_runs,
     };
 
     std::fs::write(
@@ -232,3 +178,134 @@ pub fn run(config: EstimationConfig, base_path: &str) -> Results {
 
     total_results
 }
+
+/// Mean of every response by tick with the bands of its spread across the replications
+fn chart_over_time(total_logs: &EnsembleLog, responses: &[ResponseInfo], base_path: &str) {
+    for info in responses.iter() {
+        let key = &info.key;
+        // ticks without data in any run are left out
+        let stats = total_logs
+            .column(key)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .filter(|(_, stat)| stat.count() > 0)
+            .collect::<Vec<_>>();
+
+        let ticks = stats.iter().map(|&(tick, _)| tick as f32).collect();
+        let means = stats
+            .iter()
+            .map(|(_, stat)| stat.mean().unwrap())
+            .collect::<Vec<_>>();
+        let (lower, upper) = stats.iter().map(|(_, stat)| mean_interval(stat)).unzip();
+        let (low_quantiles, high_quantiles) = stats
+            .iter()
+            .map(|(_, stat)| {
+                let [low, high] = stat.quantiles().map(Option::unwrap);
+                (low, high)
+            })
+            .unzip();
+        let (mins, maxs) = stats
+            .iter()
+            .map(|(_, stat)| (stat.min().unwrap(), stat.max().unwrap()))
+            .unzip();
+
+        let (file, title) = chart::name_of(
+            info,
+            &[
+                ("average_busy_tables", "BusyTables", "BusyTables over Time"),
+                (
+                    "average_free_workers",
+                    "FreeWorkers",
+                    "FreeWorkers Over Time",
+                ),
+                (
+                    "average_worker_waiting_time",
+                    "WaitingTime",
+                    "WaitingTime Over Time",
+                ),
+                (
+                    "dispatched_clients",
+                    "DispatchedClients",
+                    "Dispatched Clients",
+                ),
+            ],
+            || format!("{key} over Time, {}", info.unit),
+        );
+        let [low, high] = TickStat::PROBABILITIES.map(|p| p * 100.0);
+        Linear::from_data(&title, ticks, means)
+            .add_band("Min - Max", mins, maxs)
+            .add_band(
+                &format!("{low:.0}% - {high:.0}%"),
+                low_quantiles,
+                high_quantiles,
+            )
+            .add_band(
+                &format!("Mean, {:.0}% confidence", BAND_CONFIDENCE * 100.0),
+                lower,
+                upper,
+            )
+            .save(&format!("{base_path}/{file}"))
+            .unwrap();
+    }
+}
+
+/// Confidence interval of the mean across the replications at one tick,
+/// collapses to the mean with less than two replications
+fn mean_interval(stat: &TickStat) -> (f32, f32) {
+    let mean = stat.mean().unwrap_or_default();
+    let Some(variance) = stat.variance() else {
+        return (mean, mean);
+    };
+
+    let count = stat.count() as f64;
+    let t_critical = StudentsT::new(0.0, 1.0, count - 1.0)
+        .unwrap()
+        .inverse_cdf(1.0 - (1.0 - BAND_CONFIDENCE) / 2.0);
+    let half_width = (t_critical * (variance as f64 / count).sqrt()) as f32;
+
+    (mean - half_width, mean + half_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cafe, EstimationConfig};
+
+    /// Replications of the config in a pool of `threads`, written to a temporary directory
+    fn run_in_pool(config: &EstimationConfig, threads: usize) -> String {
+        let base_path = std::env::temp_dir().join(format!("saimod_experiment_{threads}"));
+        std::fs::create_dir_all(&base_path).unwrap();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let results = pool.install(|| {
+            run::<Cafe>(
+                config.simulation.clone(),
+                &config.experiment,
+                base_path.to_str().unwrap(),
+            )
+        });
+
+        toml::to_string(&results).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_results_on_any_thread_count() {
+        let raw_config = std::fs::read_to_string("config.toml").unwrap();
+        let mut config = toml::from_str::<EstimationConfig>(&raw_config).unwrap();
+        config.simulation.seed = Some(42);
+        config.simulation.use_logs = false;
+        config.experiment = ExperimentConfig {
+            total: 12,
+            min_total: 4,
+            continous: false,
+            gap_size: 0,
+            responses: Vec::new(),
+        };
+
+        assert_eq!(run_in_pool(&config, 1), run_in_pool(&config, 4));
+    }
+}
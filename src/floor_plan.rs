@@ -0,0 +1,474 @@
+use std::collections::{HashMap, VecDeque};
+
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Slider, Stroke, Vec2};
+
+use crate::{
+    client::ClientStage,
+    trace::{self, TraceEvent},
+    EntityId, EventKind, Model, Simulation, SimulationConfig, SimulationTime,
+};
+
+const CELL: f32 = 70.0;
+const TABLE_RADIUS: f32 = 20.0;
+const ENTRANCE_WIDTH: f32 = 70.0;
+const KITCHEN_WIDTH: f32 = 110.0;
+const MIN_HEIGHT: f32 = 200.0;
+/// points per second a waiter walks on the screen
+const WORKER_SPEED: f32 = 300.0;
+
+/// What a table of the floor plan shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableState {
+    Free,
+    /// held for a reservation
+    Held,
+    WaitingForWorker,
+    WaitingForFood,
+    Eating,
+}
+
+impl TableState {
+    const ALL: [TableState; 5] = [
+        Self::Free,
+        Self::Held,
+        Self::WaitingForWorker,
+        Self::WaitingForFood,
+        Self::Eating,
+    ];
+
+    fn of_stage(stage: ClientStage) -> Self {
+        match stage {
+            ClientStage::Entrance | ClientStage::WaitingForWorker | ClientStage::WaitingForBill => {
+                Self::WaitingForWorker
+            }
+            ClientStage::WaitingForFood => Self::WaitingForFood,
+            ClientStage::Eating => Self::Eating,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Free => "free",
+            Self::Held => "held",
+            Self::WaitingForWorker => "waiting for worker",
+            Self::WaitingForFood => "waiting for food",
+            Self::Eating => "eating",
+        }
+    }
+
+    fn color(self) -> Color32 {
+        match self {
+            Self::Free => Color32::DARK_GRAY,
+            Self::Held => Color32::LIGHT_BLUE,
+            Self::WaitingForWorker => Color32::RED,
+            Self::WaitingForFood => Color32::YELLOW,
+            Self::Eating => Color32::GREEN,
+        }
+    }
+}
+
+/// State of the cafe the floor plan draws
+#[derive(Debug, Clone)]
+struct Scene {
+    time: SimulationTime,
+    tables: Vec<TableState>,
+    /// table every worker is busy at, the idle ones stay at the kitchen
+    workers: Vec<Option<usize>>,
+    entrance: usize,
+}
+
+impl Scene {
+    fn of_simulation(simulation: &mut Simulation) -> Self {
+        let time = simulation.now();
+        let cafe = simulation.model_mut();
+
+        let mut tables = vec![TableState::Free; cafe.tables().len()];
+        for table in cafe.held_tables() {
+            tables[table] = TableState::Held;
+        }
+
+        let mut table_of = HashMap::new();
+        for client in cafe.clients() {
+            for (table, _) in client.seating.iter() {
+                tables[*table] = TableState::of_stage(client.stage);
+            }
+            if let Some((table, _)) = client.seating.first() {
+                table_of.insert(client.id, *table);
+            }
+        }
+
+        let levels = cafe.levels();
+        // every pending end of a task is a busy worker
+        let mut workers = cafe
+            .calendar()
+            .iter()
+            .filter(|(_, event)| {
+                matches!(
+                    event.kind,
+                    EventKind::OrderTaken | EventKind::FoodDelivered | EventKind::PaymentTaken
+                )
+            })
+            .map(|(_, event)| {
+                event
+                    .entity
+                    .and_then(|entity| table_of.get(&entity).copied())
+            })
+            .collect::<Vec<_>>();
+        workers.extend((0..levels.free_workers).map(|_| None));
+
+        Self {
+            time,
+            tables,
+            workers,
+            entrance: levels.entrance_queue,
+        }
+    }
+}
+
+/// Rebuilds the scene from a recorded trace. The trace keeps the levels only,
+/// so a group is put at the first free table and busy workers at the tables waiting for them.
+struct TraceReplay {
+    events: Vec<TraceEvent>,
+    /// index of the next event to apply
+    next: usize,
+    time: SimulationTime,
+    free_workers: u32,
+    /// workers on duty are the free and the busy ones, so the staffing of the config is not needed
+    busy_workers: u32,
+    tables: Vec<TableState>,
+    /// table and stage of every seated group
+    seated: HashMap<EntityId, (usize, ClientStage)>,
+    entrance: VecDeque<EntityId>,
+}
+
+impl TraceReplay {
+    fn new(events: Vec<TraceEvent>, config: &SimulationConfig) -> Self {
+        Self {
+            events,
+            next: 0,
+            time: 0.0,
+            free_workers: config.workers,
+            busy_workers: 0,
+            tables: vec![TableState::Free; config.table_capacities().len()],
+            seated: HashMap::new(),
+            entrance: VecDeque::new(),
+        }
+    }
+
+    /// Applies the events up to `time`
+    fn advance(&mut self, time: SimulationTime) {
+        while let Some(event) = self.events.get(self.next).copied() {
+            if event.time > time {
+                break;
+            }
+
+            self.apply(&event);
+            self.next += 1;
+        }
+
+        self.time = time;
+    }
+
+    fn apply(&mut self, event: &TraceEvent) {
+        // the event changed nothing, like the patience of a served client expiring
+        if event.before == event.after {
+            return;
+        }
+
+        let (before, after) = (event.before, event.after);
+
+        match (event.kind, event.entity) {
+            (EventKind::ReservationHold, _) => {
+                if let Some(table) = self.first(TableState::Free) {
+                    self.tables[table] = TableState::Held;
+                }
+            }
+            (EventKind::ReservationExpired, _) => {
+                if let Some(table) = self.first(TableState::Held) {
+                    self.tables[table] = TableState::Free;
+                }
+            }
+            (EventKind::DishReady | EventKind::ShiftChange, _) | (_, None) => {}
+            (EventKind::Arrival | EventKind::ReservationArrival, Some(entity)) => {
+                if after.entrance_queue > before.entrance_queue {
+                    self.entrance.push_back(entity);
+                } else {
+                    self.seat(entity, event.kind == EventKind::ReservationArrival);
+                }
+            }
+            (EventKind::EntranceReneged, Some(entity)) => self.entrance.retain(|&e| e != entity),
+            (EventKind::PatienceExpired | EventKind::PaymentTaken, Some(entity)) => {
+                if let Some((table, _)) = self.seated.remove(&entity) {
+                    self.tables[table] = TableState::Free;
+                }
+            }
+            (EventKind::OrderTaken, Some(entity)) => {
+                self.set_stage(entity, ClientStage::WaitingForFood)
+            }
+            (EventKind::FoodDelivered, Some(entity)) => self.set_stage(entity, ClientStage::Eating),
+            // waits for a worker to take one more order or the payment
+            (EventKind::ConsumptionFinished, Some(entity)) => {
+                self.set_stage(entity, ClientStage::WaitingForWorker)
+            }
+        }
+
+        // groups from the line take the freed tables
+        while self.entrance.len() > after.entrance_queue {
+            let Some(entity) = self.entrance.pop_front() else {
+                break;
+            };
+            self.seat(entity, false);
+        }
+
+        self.free_workers = after.free_workers;
+        self.busy_workers = after.busy_workers;
+    }
+
+    fn first(&self, state: TableState) -> Option<usize> {
+        self.tables.iter().position(|&table| table == state)
+    }
+
+    fn seat(&mut self, entity: EntityId, reserved: bool) {
+        let table = reserved
+            .then(|| self.first(TableState::Held))
+            .flatten()
+            .or_else(|| self.first(TableState::Free));
+
+        // shared and joined tables are not tracked
+        if let Some(table) = table {
+            self.tables[table] = TableState::WaitingForWorker;
+            self.seated
+                .insert(entity, (table, ClientStage::WaitingForWorker));
+        }
+    }
+
+    fn set_stage(&mut self, entity: EntityId, stage: ClientStage) {
+        if let Some((table, current)) = self.seated.get_mut(&entity) {
+            *current = stage;
+            self.tables[*table] = TableState::of_stage(stage);
+        }
+    }
+
+    fn scene(&self) -> Scene {
+        let busy = self.busy_workers as usize;
+
+        let mut waiting = self
+            .seated
+            .values()
+            .filter(|(_, stage)| *stage == ClientStage::WaitingForWorker)
+            .map(|(table, _)| Some(*table))
+            .collect::<Vec<_>>();
+        waiting.sort();
+
+        let mut workers = waiting.into_iter().take(busy).collect::<Vec<_>>();
+        workers.resize(busy, None);
+        workers.extend((0..self.free_workers).map(|_| None));
+
+        Scene {
+            time: self.time,
+            tables: self.tables.clone(),
+            workers,
+            entrance: self.entrance.len(),
+        }
+    }
+}
+
+enum Source {
+    Live(Box<Simulation>),
+    Trace(TraceReplay),
+}
+
+/// Animated plan of the hall, the kitchen and the entrance line
+pub struct FloorPlan {
+    source: Option<Source>,
+    playing: bool,
+    /// model time units per second of playback
+    speed: f64,
+    trace_path: String,
+    error: Option<String>,
+    /// drawn positions of the workers, they walk towards their targets
+    worker_positions: Vec<Pos2>,
+}
+
+impl Default for FloorPlan {
+    fn default() -> Self {
+        Self {
+            source: None,
+            playing: false,
+            speed: 5.0,
+            trace_path: "trace.csv".to_string(),
+            error: None,
+            worker_positions: Vec::new(),
+        }
+    }
+}
+
+impl FloorPlan {
+    pub fn ui(&mut self, ui: &mut egui::Ui, config: &SimulationConfig) {
+        ui.horizontal(|ui| {
+            if ui.button("Live run").clicked() {
+                let simulation = Simulation::with_config(config.clone());
+                self.source = Some(Source::Live(Box::new(simulation)));
+                self.worker_positions.clear();
+            }
+
+            ui.text_edit_singleline(&mut self.trace_path);
+            if ui.button("Load trace").clicked() {
+                match trace::load(&self.trace_path) {
+                    Ok(events) => {
+                        self.source = Some(Source::Trace(TraceReplay::new(events, config)));
+                        self.worker_positions.clear();
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error.to_string()),
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let label = if self.playing { "Pause" } else { "Play" };
+            if ui.button(label).clicked() {
+                self.playing = !self.playing;
+            }
+            ui.add(
+                Slider::new(&mut self.speed, 0.1..=100.0)
+                    .logarithmic(true)
+                    .text("time units per second"),
+            );
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        let Some(source) = &mut self.source else {
+            ui.label("Start a live run or load a trace");
+            return;
+        };
+
+        let dt = ui.input(|input| input.stable_dt);
+        let scene = match source {
+            Source::Live(simulation) => {
+                if self.playing {
+                    // the live run stops where the runs of the experiments end
+                    let until =
+                        (simulation.now() + self.speed * dt as f64).min(simulation.run_end());
+                    simulation.run_until(until);
+                }
+                Scene::of_simulation(simulation)
+            }
+            Source::Trace(replay) => {
+                if self.playing {
+                    replay.advance(replay.time + self.speed * dt as f64);
+                }
+                replay.scene()
+            }
+        };
+
+        self.draw(ui, &scene, dt);
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, scene: &Scene, dt: f32) {
+        ui.label(format!("Time: {:.2}", scene.time));
+        ui.horizontal(|ui| {
+            for state in TableState::ALL {
+                ui.colored_label(state.color(), state.name());
+            }
+        });
+
+        let columns = (scene.tables.len() as f32).sqrt().ceil().max(1.0) as usize;
+        let rows = scene.tables.len().div_ceil(columns).max(1);
+        let size = Vec2::new(
+            ENTRANCE_WIDTH + columns as f32 * CELL + KITCHEN_WIDTH,
+            (rows as f32 * CELL).max(MIN_HEIGHT),
+        );
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let origin = response.rect.min;
+        let font = FontId::proportional(12.0);
+
+        let table_center = |index: usize| {
+            origin
+                + Vec2::new(
+                    ENTRANCE_WIDTH + (index % columns) as f32 * CELL + CELL / 2.0,
+                    (index / columns) as f32 * CELL + CELL / 2.0,
+                )
+        };
+
+        let kitchen = Rect::from_min_size(
+            origin + Vec2::new(ENTRANCE_WIDTH + columns as f32 * CELL, 0.0),
+            Vec2::new(KITCHEN_WIDTH, size.y),
+        )
+        .shrink(4.0);
+        painter.rect_filled(kitchen, 4.0, Color32::from_gray(60));
+        painter.text(
+            kitchen.center_top() + Vec2::new(0.0, 12.0),
+            Align2::CENTER_CENTER,
+            "Kitchen",
+            font.clone(),
+            Color32::WHITE,
+        );
+
+        let entrance_x = origin.x + ENTRANCE_WIDTH / 2.0;
+        painter.text(
+            Pos2::new(entrance_x, origin.y + 12.0),
+            Align2::CENTER_CENTER,
+            "Entrance",
+            font.clone(),
+            ui.visuals().text_color(),
+        );
+        for index in 0..scene.entrance {
+            let center = Pos2::new(entrance_x, origin.y + 30.0 + index as f32 * 14.0);
+            if center.y > response.rect.max.y - 20.0 {
+                painter.text(
+                    center,
+                    Align2::CENTER_CENTER,
+                    format!("+{}", scene.entrance - index),
+                    font.clone(),
+                    ui.visuals().text_color(),
+                );
+                break;
+            }
+            painter.circle_filled(center, 5.0, Color32::LIGHT_RED);
+        }
+
+        for (index, state) in scene.tables.iter().enumerate() {
+            let center = table_center(index);
+            painter.circle_filled(center, TABLE_RADIUS, state.color());
+            painter.text(
+                center,
+                Align2::CENTER_CENTER,
+                index.to_string(),
+                font.clone(),
+                Color32::BLACK,
+            );
+        }
+
+        // workers walk towards their tables, the idle ones wait at the kitchen
+        let idle = kitchen.center();
+        self.worker_positions.resize(scene.workers.len(), idle);
+        let step = WORKER_SPEED * dt;
+        for (index, (position, target)) in self
+            .worker_positions
+            .iter_mut()
+            .zip(scene.workers.iter())
+            .enumerate()
+        {
+            let target = match target {
+                Some(table) => table_center(*table) + Vec2::new(TABLE_RADIUS, 0.0),
+                None => {
+                    idle + Vec2::new((index % 4) as f32 * 14.0 - 21.0, (index / 4) as f32 * 14.0)
+                }
+            };
+
+            let offset = target - *position;
+            *position = if offset.length() <= step {
+                target
+            } else {
+                *position + offset.normalized() * step
+            };
+
+            painter.circle_filled(*position, 6.0, Color32::WHITE);
+            painter.circle_stroke(*position, 6.0, Stroke::new(1.5, Color32::BLACK));
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::Distribution;
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct TableGroup {
+    pub count: u32,
+    pub seats: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeatingPolicy {
+    /// a group takes the smallest free table it fits at
+    #[default]
+    SmallestFitting,
+    /// a group may sit down at a table with enough free seats next to other groups
+    Sharing,
+    /// free tables are joined if there is no single table for a group
+    Joining,
+}
+
+/// Line at the door for groups which found no free table
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct EntranceConfig {
+    /// maximum count of groups in the line, everyone balks immediately if it is 0
+    #[serde(default)]
+    pub capacity: usize,
+    /// how long a group waits in the line, forever if not set
+    pub reneging_time: Option<Distribution>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub capacity: u32,
+    pub seated: u32,
+    pub groups: u32,
+}
+
+/// Table index and count of seats taken at it
+pub type Seating = Vec<(usize, u32)>;
+
+pub struct Hall {
+    tables: Vec<Table>,
+    policy: SeatingPolicy,
+}
+
+impl Hall {
+    pub fn new(capacities: Vec<u32>, policy: SeatingPolicy) -> Self {
+        let tables = capacities
+            .into_iter()
+            .map(|capacity| Table {
+                capacity,
+                seated: 0,
+                groups: 0,
+            })
+            .collect();
+
+        Self { tables, policy }
+    }
+
+    /// Seats the group according to the policy, `None` if there is no place for it
+    pub fn seat(&mut self, group_size: u32) -> Option<Seating> {
+        let seating = match self.policy {
+            SeatingPolicy::SmallestFitting => self.smallest_fitting(group_size),
+            SeatingPolicy::Sharing => self.best_shared(group_size),
+            SeatingPolicy::Joining => self
+                .smallest_fitting(group_size)
+                .or_else(|| self.joined(group_size)),
+        }?;
+
+        for (index, seats) in seating.iter() {
+            let table = &mut self.tables[*index];
+            table.seated += seats;
+            table.groups += 1;
+        }
+
+        Some(seating)
+    }
+
+    pub fn release(&mut self, seating: &[(usize, u32)]) {
+        for (index, seats) in seating.iter() {
+            let table = &mut self.tables[*index];
+            table.seated -= seats;
+            table.groups -= 1;
+        }
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    pub fn busy_tables(&self) -> u32 {
+        self.tables.iter().filter(|t| t.groups > 0).count() as u32
+    }
+
+    pub fn seated(&self) -> u32 {
+        self.tables.iter().map(|t| t.seated).sum()
+    }
+
+    pub fn total_seats(&self) -> u32 {
+        self.tables.iter().map(|t| t.capacity).sum()
+    }
+
+    fn smallest_fitting(&self, group_size: u32) -> Option<Seating> {
+        self.tables
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.groups == 0 && t.capacity >= group_size)
+            .min_by_key(|(_, t)| t.capacity)
+            .map(|(index, _)| vec![(index, group_size)])
+    }
+
+    fn best_shared(&self, group_size: u32) -> Option<Seating> {
+        self.tables
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.capacity - t.seated >= group_size)
+            .min_by_key(|(_, t)| t.capacity - t.seated)
+            .map(|(index, _)| vec![(index, group_size)])
+    }
+
+    fn joined(&self, group_size: u32) -> Option<Seating> {
+        let mut free = self
+            .tables
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.groups == 0)
+            .collect::<Vec<_>>();
+        free.sort_by_key(|(_, t)| std::cmp::Reverse(t.capacity));
+
+        let mut seating = Seating::new();
+        let mut left = group_size;
+
+        for (index, table) in free {
+            if left == 0 {
+                break;
+            }
+
+            let seats = table.capacity.min(left);
+            seating.push((index, seats));
+            left -= seats;
+        }
+
+        (left == 0).then_some(seating)
+    }
+}
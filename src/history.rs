@@ -1,45 +1,157 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::{Results, SimulationTick};
+use crate::{
+    accumulator::{P2Quantile, TallyStat},
+    response::Registry,
+    Responses, SimulationTick,
+};
 
+/// Responses of one run sampled on every tick, one column per logged response
+#[derive(Default, Clone)]
 pub struct Log {
-    pub entries: HashMap<SimulationTick, Results>,
+    /// logged responses, all the declared ones if `None`
+    selected: Option<Arc<Registry>>,
+    registry: Registry,
+    /// in the order of the registry, indexed by tick; `None` without data
+    columns: Vec<Vec<Option<f32>>>,
 }
 
 impl Log {
-    pub fn empty() -> Self {
+    /// Logs only the `selected` responses, see `Registry::subset`
+    pub fn of_selected(selected: Option<Arc<Registry>>) -> Self {
         Self {
-            entries: HashMap::new(),
+            selected,
+            ..Self::default()
+        }
+    }
+
+    /// Ticks are appended in order from zero, the first entry sets the layout
+    pub fn append(&mut self, tick: SimulationTick, entry: &impl Responses) {
+        if self.columns.is_empty() {
+            self.registry = match &self.selected {
+                Some(selected) => Registry::clone(selected),
+                None => entry.registry().clone(),
+            };
+            self.columns = vec![Vec::new(); self.registry.iter().len()];
+        }
+        assert_eq!(
+            tick as usize,
+            self.tick_count(),
+            "Ticks are logged out of order"
+        );
+
+        for (column, info) in self.columns.iter_mut().zip(self.registry.iter()) {
+            column.push(entry.get(&info.key));
+        }
+    }
+
+    pub fn tick_count(&self) -> usize {
+        self.columns.first().map_or(0, Vec::len)
+    }
+}
+
+/// Statistics of one response at one tick across the replications
+#[derive(Debug, Clone)]
+pub struct TickStat {
+    tally: TallyStat,
+    quantiles: [P2Quantile; 2],
+}
+
+impl TickStat {
+    /// Probabilities of the estimated quantiles
+    pub const PROBABILITIES: [f64; 2] = [0.05, 0.95];
+
+    fn new() -> Self {
+        Self {
+            tally: TallyStat::default(),
+            quantiles: Self::PROBABILITIES.map(P2Quantile::new),
+        }
+    }
+
+    fn observe(&mut self, value: f32) {
+        self.tally.observe(value as f64);
+        for quantile in self.quantiles.iter_mut() {
+            quantile.observe(value as f64);
         }
     }
 
-    pub fn append(&mut self, tick: SimulationTick, entry: Results) {
-        let _ = self.entries.insert(tick, entry);
+    /// Replications which had data at the tick
+    pub fn count(&self) -> usize {
+        self.tally.count()
     }
 
-    ///ordered by simulation time
-    pub fn iter(&self) -> impl Iterator<Item = (SimulationTick, &Results)> {
-        let mut ticks = self.entries.keys().copied().collect::<Vec<_>>();
-        ticks.sort_unstable();
+    pub fn mean(&self) -> Option<f32> {
+        self.tally.mean()
+    }
+
+    pub fn variance(&self) -> Option<f32> {
+        self.tally.variance()
+    }
+
+    pub fn min(&self) -> Option<f32> {
+        self.tally.min()
+    }
+
+    pub fn max(&self) -> Option<f32> {
+        self.tally.max()
+    }
+
+    /// In the order of `PROBABILITIES`
+    pub fn quantiles(&self) -> [Option<f32>; 2] {
+        self.quantiles
+            .each_ref()
+            .map(|quantile| quantile.value().map(|value| value as f32))
+    }
+}
 
-        ticks.into_iter().map(|tick| {
-            let entry = self.entries.get(&tick).unwrap();
-            (tick, entry)
-        })
+/// Logs of the replications merged tick by tick.
+/// Runs of different length are aligned at their start.
+#[derive(Default)]
+pub struct EnsembleLog {
+    registry: Registry,
+    /// in the order of the registry, indexed by tick
+    columns: Vec<Vec<TickStat>>,
+}
+
+impl EnsembleLog {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, log: &Log) {
+        if log.columns.is_empty() {
+            return;
+        }
+        if self.columns.is_empty() {
+            self.registry = log.registry.clone();
+            self.columns = vec![Vec::new(); log.columns.len()];
+        }
+        assert_eq!(
+            self.columns.len(),
+            log.columns.len(),
+            "Logs of different models are added"
+        );
+
+        for (column, values) in self.columns.iter_mut().zip(log.columns.iter()) {
+            if column.len() < values.len() {
+                column.resize_with(values.len(), TickStat::new);
+            }
+            for (stat, value) in column.iter_mut().zip(values) {
+                if let Some(value) = value {
+                    stat.observe(*value);
+                }
+            }
+        }
     }
 
-    pub fn add_mut(&mut self, other: Self) {
-        other.entries.into_iter().for_each(|(tick, entry)| {
-            self.entries
-                .entry(tick)
-                .and_modify(|e| e.add_mut(entry.clone()))
-                .or_insert(entry);
-        });
+    pub fn tick_count(&self) -> usize {
+        self.columns.first().map_or(0, Vec::len)
     }
 
-    pub fn norm_mut(&mut self, count: usize) {
-        self.entries
-            .values_mut()
-            .for_each(|entry| entry.norm_mut(count));
+    /// Statistics of the response by tick, `None` if it is not declared
+    pub fn column(&self, key: &str) -> Option<&[TickStat]> {
+        self.registry
+            .index_of(key)
+            .map(|index| self.columns[index].as_slice())
     }
 }
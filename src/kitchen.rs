@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use crate::{EntityId, SimulationTime};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueDiscipline {
+    #[default]
+    Fifo,
+    /// orders with the shortest production time are cooked first
+    Priority,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct KitchenConfig {
+    /// cooks working in parallel at the main line, it is unlimited if not set
+    pub cooks: Option<u32>,
+    #[serde(default)]
+    pub discipline: QueueDiscipline,
+    /// production time grows by this share for every order waiting in the queue of the station
+    #[serde(default)]
+    pub workload_factor: f64,
+    /// separate stations (grill, bar, ...) with their own cooks and queues
+    #[serde(default)]
+    pub stations: Vec<StationConfig>,
+}
+
+impl KitchenConfig {
+    /// Index of the station by name, the main line is 0
+    pub fn station_index(&self, name: Option<&str>) -> usize {
+        match name {
+            None => 0,
+            Some(name) => {
+                1 + self
+                    .stations
+                    .iter()
+                    .position(|station| station.name == name)
+                    .unwrap_or_else(|| panic!("Unknown kitchen station {name}"))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StationConfig {
+    pub name: String,
+    /// unlimited if not set
+    pub cooks: Option<u32>,
+}
+
+/// One dish to cook
+#[derive(Debug, Clone)]
+pub struct Order {
+    pub entity: EntityId,
+    /// index of the dish in the menu, `None` when there is no menu
+    pub dish: Option<usize>,
+    pub station: usize,
+    pub placed: SimulationTime,
+    pub production_time: SimulationTime,
+    /// priority of the client class, higher is cooked first
+    pub priority: u32,
+}
+
+struct Station {
+    cooks: Option<u32>,
+    busy_cooks: u32,
+    queue: VecDeque<Order>,
+}
+
+impl Station {
+    fn new(cooks: Option<u32>) -> Self {
+        Self {
+            cooks,
+            busy_cooks: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn is_free(&self) -> bool {
+        self.cooks.is_none_or(|cooks| self.busy_cooks < cooks)
+    }
+}
+
+pub struct Kitchen {
+    discipline: QueueDiscipline,
+    workload_factor: f64,
+    /// the main line goes first
+    stations: Vec<Station>,
+}
+
+impl Kitchen {
+    pub fn new(config: KitchenConfig) -> Self {
+        let stations = std::iter::once(config.cooks)
+            .chain(config.stations.iter().map(|station| station.cooks))
+            .map(Station::new)
+            .collect();
+
+        Self {
+            discipline: config.discipline,
+            workload_factor: config.workload_factor,
+            stations,
+        }
+    }
+
+    pub fn place(&mut self, order: Order) {
+        self.stations[order.station].queue.push_back(order);
+    }
+
+    /// Takes the next order at any station with a free cook, production time accounts the workload
+    pub fn start_next(&mut self) -> Option<Order> {
+        let discipline = self.discipline;
+        let workload_factor = self.workload_factor;
+
+        self.stations
+            .iter_mut()
+            .filter(|station| station.is_free())
+            .find_map(|station| {
+                let top = station.queue.iter().map(|order| order.priority).max()?;
+                let mut candidates = station
+                    .queue
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, order)| order.priority == top);
+
+                let position = match discipline {
+                    QueueDiscipline::Fifo => candidates.next()?.0,
+                    QueueDiscipline::Priority => {
+                        candidates
+                            .min_by(|(_, a), (_, b)| {
+                                a.production_time.total_cmp(&b.production_time)
+                            })?
+                            .0
+                    }
+                };
+                let mut order = station.queue.remove(position)?;
+
+                order.production_time *= 1.0 + workload_factor * station.queue.len() as f64;
+                station.busy_cooks += 1;
+
+                Some(order)
+            })
+    }
+
+    pub fn finish(&mut self, station: usize) {
+        self.stations[station].busy_cooks -= 1;
+    }
+
+    pub fn queue_len(&self) -> usize {
+        self.stations
+            .iter()
+            .map(|station| station.queue.len())
+            .sum()
+    }
+
+    /// Share of busy cooks at the stations with limited cooks, always zero for the unlimited kitchen
+    pub fn utilisation(&self) -> f32 {
+        let (busy, cooks) = self
+            .stations
+            .iter()
+            .filter_map(|station| Some((station.busy_cooks, station.cooks?)))
+            .fold((0, 0), |(busy, cooks), (b, c)| (busy + b, cooks + c));
+
+        if cooks > 0 {
+            busy as f32 / cooks as f32
+        } else {
+            0.0
+        }
+    }
+}
@@ -27,30 +27,54 @@
 // Каждому выбрать по отклику
 // отклик должен стабилизироваться
 
+mod accumulator;
 mod app;
+mod arrival;
+mod batch;
+mod calendar;
 mod chart;
+mod client;
 mod config;
+mod debugger;
+mod distribution;
+mod economics;
 pub mod egui_charts;
+mod engine;
 mod event;
 mod experiment;
+mod floor_plan;
+mod hall;
+mod kitchen;
+mod menu;
 mod history;
+mod process;
+mod reservation;
+mod response;
 mod results;
+mod rng;
 mod scenario;
 mod simulation;
+mod staff;
 mod statistic;
 pub mod tasks;
+mod trace;
 
 use std::fs;
 
 use app::EguiApp;
-pub use config::{EstimationConfig, SimulationConfig};
-pub use event::Event;
+pub use calendar::Calendar;
+pub use client::{Client, ClientClass, DepartureReason};
+pub use config::{EstimationConfig, ParameterKind, SimulationConfig};
+pub use distribution::Distribution;
+pub use engine::{Engine, Model, ModelConfig, Responses, SimulationTick, SimulationTime};
+pub use event::{EntityId, Event, EventKind};
 pub use experiment::ExperimentConfig;
-pub use history::Log;
+pub use history::{EnsembleLog, Log};
+use process::cafe::ProcessCafe;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 pub use results::Results;
 use scenario::{ScenarioConfig, ScenarioParameter};
-pub use simulation::{Simulation, SimulationTick};
+pub use simulation::{Cafe, Simulation};
 pub use statistic::Stats;
 
 fn asdfmain() {
@@ -76,10 +100,26 @@ fn main() -> anyhow::Result<()> {
     //     .filter_level(log::LevelFilter::Info)
     //     .init();
 
-    let config = {
+    let mut config = {
         let raw_config = std::fs::read_to_string("config.toml").expect("Failed to read config");
         toml::from_str::<EstimationConfig>(&raw_config).expect("Failed to parse config")
     };
+    // all tasks share one master seed, so the whole run can be reproduced from it
+    config.simulation.seed = Some(rng::resolve_seed(config.simulation.seed));
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|arg| arg == "--gui") {
+        asdfmain();
+        return Ok(());
+    }
+    config.simulation.trace.apply_args(&args)?;
+    if config.simulation.trace.enabled {
+        // the trace is written by one run instead of the tasks
+        let mut sim = Simulation::with_config(config.simulation.clone());
+        sim.run();
+
+        return Ok(());
+    }
 
     let directories = vec![
         "stats/3_1",
@@ -93,6 +133,7 @@ fn main() -> anyhow::Result<()> {
         "stats/4_2/2",
         "stats/4_2/3",
         "stats/4_3",
+        "stats/process",
     ];
 
     for dir_path in directories.into_iter() {
@@ -103,7 +144,16 @@ fn main() -> anyhow::Result<()> {
     }
 
     let tasks = [
-        task_3_1, task_3_2, task_3_3, task_3_4, task_3_5, task_3_6, task_4_1, task_4_2, task_4_3,
+        task_3_1,
+        task_3_2,
+        task_3_3,
+        task_3_4,
+        task_3_5,
+        task_3_6,
+        task_4_1,
+        task_4_2,
+        task_4_3,
+        task_process,
     ];
     tasks.into_par_iter().for_each(|task| task(&config));
 
@@ -112,98 +162,140 @@ fn main() -> anyhow::Result<()> {
 
 fn task_3_1(config: &EstimationConfig) {
     let mut total_results = Results::zeros();
-    let mut total_logs = Log::empty();
     let mut results = Vec::<Results>::new();
+    let mut sojourn_times = Vec::<f32>::new();
 
+    let seed = rng::resolve_seed(config.simulation.seed);
     let tmp = (0..config.experiment.total)
         .into_par_iter()
-        .map(|_| {
-            let mut sim = Simulation::with_config(config.simulation.clone());
-            sim.run()
+        .map(|i| {
+            let mut sim = Simulation::with_stream(config.simulation.clone(), seed, i as u64);
+            sim.run_with_clients()
         })
         .collect::<Vec<_>>();
 
-    tmp.into_iter().for_each(|(run_result, run_log)| {
+    tmp.into_iter().for_each(|(run_result, _run_log, clients)| {
         total_results.add_mut(run_result.clone());
-        total_logs.add_mut(run_log);
 
         results.push(run_result);
+        sojourn_times.extend(
+            clients
+                .iter()
+                .filter(|c| c.departure_reason == Some(DepartureReason::Served))
+                .filter_map(|c| c.sojourn_time())
+                .map(|t| t as f32),
+        );
     });
 
     total_results.norm_mut(config.experiment.total);
-    total_logs.norm_mut(config.experiment.total);
 
-    chart::Histogram::from_y_data(
-        "Среднее кол-во свободных работников",
-        results.iter().map(|r| r.average_free_workers).collect(),
-    )
-    .save("stats/3_1/average_free_workers", &config.stats)
-    .unwrap();
+    for info in total_results
+        .registry()
+        .select(&config.experiment.responses)
+    {
+        // runs without data are left out of the histogram
+        let values = results
+            .iter()
+            .filter_map(|r| r.get(&info.key))
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            log::warn!("No run had data for {}", info.key);
+            continue;
+        }
 
-    chart::Histogram::from_y_data(
-        "Кол-во обслуженных клиентов",
-        results.iter().map(|r| r.dispatched_clients).collect(),
-    )
-    .save("stats/3_1/dispatched_clients", &config.stats)
-    .unwrap();
+        let (file, title) = chart::name_of(
+            info,
+            &[
+                (
+                    "average_free_workers",
+                    "average_free_workers",
+                    "Среднее кол-во свободных работников",
+                ),
+                (
+                    "dispatched_clients",
+                    "dispatched_clients",
+                    "Кол-во обслуженных клиентов",
+                ),
+                (
+                    "average_worker_waiting_time",
+                    "waiting_time",
+                    "Время ожидания работника",
+                ),
+            ],
+            || format!("{}, {}", info.key, info.unit),
+        );
+        chart::Histogram::from_y_data(&title, values)
+            .save(&format!("stats/3_1/{file}"), &config.stats)
+            .unwrap();
+    }
 
-    chart::Histogram::from_y_data(
-        "Время ожидания работника",
-        results.iter().map(|r| r.average_worker_waiting_time).collect(),
-    )
-    .save("stats/3_1/waiting_time", &config.stats)
-    .unwrap();
+    if !sojourn_times.is_empty() {
+        chart::Histogram::from_y_data("Время пребывания обслуженного клиента", sojourn_times)
+            .save("stats/3_1/sojourn_time", &config.stats)
+            .unwrap();
+    }
+
+    rng::save_seed("stats/3_1", seed).unwrap();
 }
 
 // Интервальная оценка двух непрерывных и одного дискретного откликов
 fn task_3_2(config: &EstimationConfig) {
     let total = 10;
     let mut results = vec![];
+    let seed = rng::resolve_seed(config.simulation.seed);
 
-    for _ in 0..total {
-        let mut sim = Simulation::with_config(config.simulation.clone());
+    for i in 0..total {
+        let mut sim = Simulation::with_stream(config.simulation.clone(), seed, i as u64);
         let (run_result, _run_log) = sim.run();
         results.push(run_result);
     }
 
-    chart::Linear::from_data(
-        "Свободные работники от времени симуляции",
-        (0..total).map(|v| v as f32).collect(),
-        results.iter().map(|r| r.average_free_workers).collect(),
-    )
-    .use_approximation(false)
-    .set_config(&config.stats)
-    .save("stats/3_2/free_workers")
-    .unwrap();
-
-    chart::Linear::from_data(
-        "Кол-во обслуженных клиентов",
-        (0..total).map(|v| v as f32).collect(),
-        results.iter().map(|r| r.dispatched_clients).collect(),
-    )
-    .use_approximation(false)
-    .set_config(&config.stats)
-    .save("stats/3_2/dispatched_clients")
-    .unwrap();
+    for info in results[0].registry().select(&config.experiment.responses) {
+        let (runs, values) = results
+            .iter()
+            .enumerate()
+            .filter_map(|(run, r)| Some((run as f32, r.get(&info.key)?)))
+            .unzip();
+
+        let (file, title) = chart::name_of(
+            info,
+            &[
+                (
+                    "average_free_workers",
+                    "free_workers",
+                    "Свободные работники от времени симуляции",
+                ),
+                (
+                    "dispatched_clients",
+                    "dispatched_clients",
+                    "Кол-во обслуженных клиентов",
+                ),
+                (
+                    "average_worker_waiting_time",
+                    "waiting_time",
+                    "Среднее время ожидания работника",
+                ),
+            ],
+            || format!("{} of the runs, {}", info.key, info.unit),
+        );
+        chart::Linear::from_data(&title, runs, values)
+            .use_approximation(false)
+            .set_config(&config.stats)
+            .save(&format!("stats/3_2/{file}"))
+            .unwrap();
+    }
 
-    chart::Linear::from_data(
-        "Среднее время ожидания работника",
-        (0..total).map(|v| v as f32).collect(),
-        results.iter().map(|r| r.average_worker_waiting_time).collect(),
-    )
-    .use_approximation(false)
-    .set_config(&config.stats)
-    .save("stats/3_2/waiting_time")
-    .unwrap();
+    rng::save_seed("stats/3_2", seed).unwrap();
 }
 
 fn task_3_3(config: &EstimationConfig) {
     let total = 100;
     let window_size = 2;
     let mut results = vec![];
+    let seed = rng::resolve_seed(config.simulation.seed);
 
-    for _ in 0..(total + window_size) {
-        let mut sim = Simulation::with_config(config.simulation.clone());
+    for i in 0..(total + window_size) {
+        let mut sim = Simulation::with_stream(config.simulation.clone(), seed, i as u64);
         let (run_result, _run_log) = sim.run();
         results.push(run_result);
     }
@@ -215,76 +307,83 @@ fn task_3_3(config: &EstimationConfig) {
         values.push(window);
     }
 
-    chart::Linear::from_data(
-        "Свободные работники от времени симуляции",
-        (0..total).map(|v| v as f32).collect(),
-        values
-            .iter()
-            .map(|data| {
-                let processed = data
-                    .iter()
-                    .map(|r| r.average_free_workers as f64)
-                    .collect::<Vec<_>>();
-
-                let stats = Stats::new(&processed, &config.stats);
-                (2.0 * stats.std_dev * stats.t_stat) as f32 / (data.len() as f32).sqrt()
-            })
-            .collect(),
-    )
-    .save("stats/3_3/free_workers")
-    .unwrap();
-
-    chart::Linear::from_data(
-        "Кол-во обслуженных клиентов over Time",
-        (0..total).map(|v| v as f32).collect(),
-        values
+    for info in results[0].registry().select(&config.experiment.responses) {
+        // windows with less than two runs with data have no interval
+        let (windows, widths) = values
             .iter()
-            .map(|data| {
+            .enumerate()
+            .filter_map(|(window, data)| {
                 let processed = data
                     .iter()
-                    .map(|r| r.dispatched_clients as f64)
+                    .filter_map(|r| r.get(&info.key))
+                    .map(|value| value as f64)
                     .collect::<Vec<_>>();
+                if processed.len() < 2 {
+                    return None;
+                }
 
                 let stats = Stats::new(&processed, &config.stats);
-                (2.0 * stats.std_dev * stats.t_stat) as f32 / (data.len() as f32).sqrt()
+                let width =
+                    (2.0 * stats.std_dev * stats.t_stat) as f32 / (processed.len() as f32).sqrt();
+                Some((window as f32, width))
             })
-            .collect(),
-    )
-    .save("stats/3_3/DispatchedClients")
-    .unwrap();
+            .unzip();
+
+        let (file, title) = chart::name_of(
+            info,
+            &[
+                (
+                    "average_free_workers",
+                    "free_workers",
+                    "Свободные работники от времени симуляции",
+                ),
+                (
+                    "dispatched_clients",
+                    "DispatchedClients",
+                    "Кол-во обслуженных клиентов over Time",
+                ),
+                (
+                    "average_worker_waiting_time",
+                    "waiting_time",
+                    "Waiting time",
+                ),
+            ],
+            || format!("Confidence interval of {} from the runs", info.key),
+        );
+        chart::Linear::from_data(&title, windows, widths)
+            .save(&format!("stats/3_3/{file}"))
+            .unwrap();
+    }
 
-    chart::Linear::from_data(
-        "Waiting time",
-        (0..total).map(|v| v as f32).collect(),
-        values
-            .iter()
-            .map(|data| {
-                let processed = data
-                    .iter()
-                    .map(|r| r.average_worker_waiting_time as f64)
-                    .collect::<Vec<_>>();
+    rng::save_seed("stats/3_3", seed).unwrap();
+}
 
-                let stats = Stats::new(&processed, &config.stats);
-                (2.0 * stats.std_dev * stats.t_stat) as f32 / (data.len() as f32).sqrt()
-            })
-            .collect(),
-    )
-    .save("stats/3_3/waiting_time")
-    .unwrap();
+/// Responses the scenarios chart, see `[scenario] responses` in the config
+fn scenario_responses(config: &EstimationConfig) -> Vec<String> {
+    config
+        .scenario
+        .as_ref()
+        .map(|scenario| scenario.responses.clone())
+        .unwrap_or_default()
 }
 
 // Change variable and see difference
 fn task_3_4(config: &EstimationConfig) {
-    let mut config = config.clone();
-    config.scenario = Some(ScenarioConfig {
+    let scenario = ScenarioConfig {
         parameters: vec![ScenarioParameter {
-            kind: scenario::ParameterKind::Clients,
+            kind: ParameterKind::Clients,
             values: 30..80,
             step: 1,
         }],
-    });
+        responses: scenario_responses(config),
+    };
 
-    scenario::run(config, "3_4");
+    scenario::run::<Cafe>(
+        config.simulation.clone(),
+        &scenario,
+        &config.experiment,
+        "3_4",
+    );
 }
 
 fn task_3_5(config: &EstimationConfig) {
@@ -292,7 +391,7 @@ fn task_3_5(config: &EstimationConfig) {
     config.experiment.continous = false;
     config.experiment.total = 10_000;
 
-    experiment::run(config, "stats/3_5/");
+    experiment::run::<Cafe>(config.simulation, &config.experiment, "stats/3_5/");
 }
 
 fn task_3_6(config: &EstimationConfig) {
@@ -300,20 +399,25 @@ fn task_3_6(config: &EstimationConfig) {
     config.experiment.continous = true;
     config.experiment.total = 10_000;
 
-    experiment::run(config, "stats/3_6/");
+    experiment::run::<Cafe>(config.simulation, &config.experiment, "stats/3_6/");
 }
 
 fn task_4_1(config: &EstimationConfig) {
-    let mut config = config.clone();
-    config.scenario = Some(ScenarioConfig {
+    let scenario = ScenarioConfig {
         parameters: vec![ScenarioParameter {
-            kind: scenario::ParameterKind::Production, // Production
+            kind: ParameterKind::Production, // Production
             values: 3..15,
             step: 1,
         }],
-    });
+        responses: scenario_responses(config),
+    };
 
-    scenario::run(config, "4_1");
+    scenario::run::<Cafe>(
+        config.simulation.clone(),
+        &scenario,
+        &config.experiment,
+        "4_1",
+    );
 }
 
 fn task_4_2(config: &EstimationConfig) {
@@ -321,78 +425,74 @@ fn task_4_2(config: &EstimationConfig) {
     config.experiment.continous = false;
 
     config.simulation.workers = 2;
-    config.simulation.dancing_time = 1..4;
-    let r1 = experiment::run(config.clone(), "stats/4_2/1");
+    config.simulation.dancing_time = Distribution::uniform(1.0, 4.0);
+    let r1 = experiment::run::<Cafe>(config.simulation.clone(), &config.experiment, "stats/4_2/1");
 
     config.simulation.workers = 5;
-    config.simulation.dancing_time = 2..8;
-    let r2 = experiment::run(config.clone(), "stats/4_2/2");
+    config.simulation.dancing_time = Distribution::uniform(2.0, 8.0);
+    let r2 = experiment::run::<Cafe>(config.simulation.clone(), &config.experiment, "stats/4_2/2");
 
     config.simulation.workers = 10;
-    config.simulation.dancing_time = 4..12;
-    let r3 = experiment::run(config.clone(), "stats/4_2/3");
-
-    chart::Bar::from_y_data(
-        "BusyTables",
-        vec![
-            r1.average_busy_tables,
-            r2.average_busy_tables,
-            r3.average_busy_tables,
-        ],
-    )
-    .save("stats/4_2/BusyTables")
-    .unwrap();
-
-    chart::Bar::from_y_data(
-        "FreeWorkers",
-        vec![
-            r1.average_free_workers,
-            r2.average_free_workers,
-            r3.average_free_workers,
-        ],
-    )
-    .save("stats/4_2/FreeWorkers")
-    .unwrap();
-
-    chart::Bar::from_y_data(
-        "WaitingTime",
-        vec![
-            r1.average_worker_waiting_time,
-            r2.average_worker_waiting_time,
-            r3.average_worker_waiting_time,
-        ],
-    )
-    .save("stats/4_2/WaitingTime")
-    .unwrap();
+    config.simulation.dancing_time = Distribution::uniform(4.0, 12.0);
+    let r3 = experiment::run::<Cafe>(config.simulation.clone(), &config.experiment, "stats/4_2/3");
+
+    for info in r1.registry().select(&config.experiment.responses) {
+        let (file, title) = chart::name_of(
+            info,
+            &[
+                ("average_busy_tables", "BusyTables", "BusyTables"),
+                ("average_free_workers", "FreeWorkers", "FreeWorkers"),
+                ("average_worker_waiting_time", "WaitingTime", "WaitingTime"),
+                (
+                    "dispatched_clients",
+                    "DispatchedClients",
+                    "DispatchedClients",
+                ),
+            ],
+            || format!("{}, {}", info.key, info.unit),
+        );
+        // configurations without data have no bar
+        let values = [&r1, &r2, &r3].map(|r| r.get(&info.key)).to_vec();
+        if values.iter().all(Option::is_none) {
+            log::warn!("No run had data for {}", info.key);
+            continue;
+        }
 
-    chart::Bar::from_y_data(
-        "DispatchedClients",
-        vec![
-            r1.dispatched_clients,
-            r2.dispatched_clients,
-            r3.dispatched_clients,
-        ],
-    )
-    .save("stats/4_2/DispatchedClients")
-    .unwrap();
+        chart::Bar::from_y_data(&title, values)
+            .save(&format!("stats/4_2/{file}"))
+            .unwrap();
+    }
 }
 
 fn task_4_3(config: &EstimationConfig) {
-    let mut config = config.clone();
-    config.scenario = Some(ScenarioConfig {
+    let scenario = ScenarioConfig {
         parameters: vec![
             ScenarioParameter {
-                kind: scenario::ParameterKind::Production,
+                kind: ParameterKind::Production,
                 values: 2..6,
                 step: 1,
             },
             ScenarioParameter {
-                kind: scenario::ParameterKind::Dancing,
+                kind: ParameterKind::Dancing,
                 values: 2..8,
                 step: 1,
             },
         ],
-    });
+        responses: scenario_responses(config),
+    };
+
+    scenario::run::<Cafe>(
+        config.simulation.clone(),
+        &scenario,
+        &config.experiment,
+        "4_3",
+    );
+}
+
+// the same experiment with the process-oriented version of the cafe
+fn task_process(config: &EstimationConfig) {
+    let mut config = config.clone();
+    config.experiment.continous = false;
 
-    scenario::run(config, "4_3");
+    experiment::run::<ProcessCafe>(config.simulation, &config.experiment, "stats/process/");
 }
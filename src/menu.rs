@@ -0,0 +1,70 @@
+use rand::Rng;
+
+use crate::Distribution;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Dish {
+    pub name: String,
+    #[serde(default)]
+    pub category: String,
+    /// relative weight of the dish among the ordered ones
+    #[serde(default = "default_popularity")]
+    pub popularity: f64,
+    pub production_time: Distribution,
+    /// added to the order revenue
+    #[serde(default)]
+    pub price: f64,
+    /// kitchen station cooking the dish, the main line if not set
+    pub station: Option<String>,
+}
+
+fn default_popularity() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Menu {
+    /// count of dishes in one order (rounded, at least 1)
+    #[serde(default = "single_dish")]
+    pub dishes_per_order: Distribution,
+    #[serde(default)]
+    pub dishes: Vec<Dish>,
+}
+
+fn single_dish() -> Distribution {
+    Distribution::constant(1.0)
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self {
+            dishes_per_order: single_dish(),
+            dishes: Vec::new(),
+        }
+    }
+}
+
+impl Menu {
+    pub fn is_empty(&self) -> bool {
+        self.dishes.is_empty()
+    }
+
+    /// Index of a random dish according to the popularity
+    pub fn pick<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let total = self.dishes.iter().map(|dish| dish.popularity).sum::<f64>();
+        let mut left = rng.random::<f64>() * total;
+
+        for (index, dish) in self.dishes.iter().enumerate() {
+            if left < dish.popularity {
+                return index;
+            }
+            left -= dish.popularity;
+        }
+
+        self.dishes.len() - 1
+    }
+
+    pub fn dishes_count<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
+        self.dishes_per_order.sample(rng).round().max(1.0) as u32
+    }
+}
@@ -0,0 +1,417 @@
+use std::{collections::HashMap, rc::Rc, sync::Arc};
+
+use rand::prelude::*;
+
+use super::{Context, ProcessId, ResourceId, Scheduler, Wakeup};
+use crate::{
+    accumulator::{TallyStat, TimeWeightedStat},
+    arrival::Arrivals,
+    engine::Model,
+    response::{Registry, ResponseKind},
+    rng::SimRng,
+    Calendar, Distribution, Results, SimulationConfig, SimulationTime,
+};
+
+/// State shared by the processes of the cafe
+pub struct CafeState {
+    rng: SimRng,
+    arrivals: Arrivals,
+    /// dishes of the current order of the client which are not cooked yet
+    pending_dishes: HashMap<ProcessId, u32>,
+
+    worker_waiting_time: TallyStat,
+    order_time: TallyStat,
+    consumption_time: TallyStat,
+
+    dispatched_clients: usize,
+    not_dispatched_clients: usize,
+    immediately_left_clients: usize,
+}
+
+impl CafeState {
+    fn sample(&mut self, distribution: &Distribution) -> SimulationTime {
+        distribution.sample(&mut self.rng)
+    }
+
+    fn next_arrival(&mut self, after: Option<SimulationTime>) -> Option<SimulationTime> {
+        self.arrivals.next(after, &mut self.rng)
+    }
+
+    fn batch_size(&mut self) -> u32 {
+        self.arrivals.batch_size(&mut self.rng)
+    }
+}
+
+/// What every process of the cafe needs to know
+struct Setup {
+    config: SimulationConfig,
+    /// one table per group
+    tables: ResourceId,
+    waiters: ResourceId,
+    /// the main line first, then the stations of the kitchen
+    stations: Vec<ResourceId>,
+}
+
+/// The cafe written as processes: clients hold tables, waiters and cooks as resources.
+/// Covers arrivals, ordering with reorders, the kitchen and payment;
+/// the entrance line, shifts, reservations and client classes stay in the event-based `Cafe`.
+pub struct ProcessCafe {
+    scheduler: Scheduler<CafeState>,
+    setup: Rc<Setup>,
+    registry: Arc<Registry>,
+
+    busy_tables: TimeWeightedStat,
+    free_workers: TimeWeightedStat,
+    kitchen_utilisation: TimeWeightedStat,
+    order_queue: TimeWeightedStat,
+}
+
+impl Model for ProcessCafe {
+    type Config = SimulationConfig;
+    type Event = Wakeup;
+    type Responses = Results;
+
+    fn new(config: SimulationConfig, rng: SimRng) -> Self {
+        let state = CafeState {
+            rng,
+            arrivals: Arrivals::new(
+                config.arrivals.clone(),
+                config.client_ratio,
+                config.max_time,
+            ),
+            pending_dishes: HashMap::new(),
+            worker_waiting_time: TallyStat::default(),
+            order_time: TallyStat::default(),
+            consumption_time: TallyStat::default(),
+            dispatched_clients: 0,
+            not_dispatched_clients: 0,
+            immediately_left_clients: 0,
+        };
+
+        let mut scheduler = Scheduler::new(state, config.events_capacity);
+        let tables = scheduler.add_resource(Some(config.table_capacities().len() as u32));
+        let waiters = scheduler.add_resource(Some(config.workers));
+        let mut stations = vec![scheduler.add_resource(config.kitchen.cooks)];
+        for station in config.kitchen.stations.iter() {
+            stations.push(scheduler.add_resource(station.cooks));
+        }
+
+        let workers = config.workers;
+        Self {
+            registry: Arc::new(Self::declare_responses(&config)),
+            scheduler,
+            setup: Rc::new(Setup {
+                config,
+                tables,
+                waiters,
+                stations,
+            }),
+            busy_tables: TimeWeightedStat::new(0.0, 0.0),
+            free_workers: TimeWeightedStat::new(0.0, workers as f64),
+            kitchen_utilisation: TimeWeightedStat::new(0.0, 0.0),
+            order_queue: TimeWeightedStat::new(0.0, 0.0),
+        }
+    }
+
+    fn start(&mut self) {
+        let process = arrivals(self.scheduler.context(), self.setup.clone());
+        self.scheduler.spawn(process);
+    }
+
+    fn calendar(&mut self) -> &mut Calendar<Wakeup> {
+        self.scheduler.calendar()
+    }
+
+    fn handle(&mut self, time: SimulationTime, wakeup: Wakeup) {
+        self.scheduler.resume(time, wakeup);
+        self.update_levels(time);
+    }
+
+    fn observe(&mut self, time: SimulationTime) {
+        for stat in [
+            &mut self.busy_tables,
+            &mut self.free_workers,
+            &mut self.kitchen_utilisation,
+            &mut self.order_queue,
+        ] {
+            stat.advance(time);
+        }
+    }
+
+    fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    fn responses(&self) -> Results {
+        self.results(Results::new(self.registry.clone()))
+    }
+
+    fn selected_responses(&self, selected: &Arc<Registry>) -> Results {
+        self.results(Results::of_selected(
+            self.registry.clone(),
+            selected.clone(),
+        ))
+    }
+
+    fn reset_metrics(&mut self) {
+        self.busy_tables.reset();
+        self.free_workers.reset();
+        self.kitchen_utilisation.reset();
+        self.order_queue.reset();
+
+        let mut state = self.scheduler.state_mut();
+        state.worker_waiting_time = TallyStat::default();
+        state.order_time = TallyStat::default();
+        state.consumption_time = TallyStat::default();
+        state.dispatched_clients = 0;
+        state.not_dispatched_clients = 0;
+        state.immediately_left_clients = 0;
+    }
+}
+
+impl ProcessCafe {
+    /// The responses of the event-based `Cafe` which the processes cover
+    pub fn declare_responses(config: &SimulationConfig) -> Registry {
+        use ResponseKind::{Continuous, Discrete};
+
+        let mut registry = Registry::default();
+
+        registry.declare_with_spread("average_worker_waiting_time", "time", Discrete);
+        registry.declare_with_spread("average_order_time", "time", Discrete);
+        registry.declare_with_spread("average_consumption_time", "time", Discrete);
+        registry.declare_with_spread("average_busy_tables", "tables", Continuous);
+        registry.declare_with_spread("average_free_workers", "workers", Continuous);
+        registry.declare_with_spread("average_order_queue", "dishes", Continuous);
+
+        registry.declare("kitchen_utilisation", "share", Continuous);
+
+        for count in [
+            "dispatched_clients",
+            "not_dispatched_clients",
+            "immediately_left_clients_count",
+        ] {
+            registry.declare(count, "clients", Discrete);
+        }
+
+        registry.declare_time_shares("busy_tables", config.table_capacities().len());
+
+        registry
+    }
+
+    /// Sets the responses of the run on `results`, which may select only some of them
+    fn results(&self, mut results: Results) -> Results {
+        let state = self.scheduler.state();
+
+        results.set_tally("average_worker_waiting_time", &state.worker_waiting_time);
+        results.set_tally("average_order_time", &state.order_time);
+        results.set_tally("average_consumption_time", &state.consumption_time);
+        results.set_time_weighted("average_busy_tables", &self.busy_tables);
+        results.set_time_weighted("average_free_workers", &self.free_workers);
+        results.set_time_weighted("average_order_queue", &self.order_queue);
+        // an unlimited kitchen is never busy
+        let limited_kitchen = self
+            .setup
+            .stations
+            .iter()
+            .any(|&station| self.scheduler.capacity(station) < u32::MAX);
+        results.set(
+            "kitchen_utilisation",
+            limited_kitchen.then(|| self.kitchen_utilisation.mean()),
+        );
+        results.set("dispatched_clients", state.dispatched_clients as f32);
+        results.set(
+            "not_dispatched_clients",
+            state.not_dispatched_clients as f32,
+        );
+        results.set(
+            "immediately_left_clients_count",
+            state.immediately_left_clients as f32,
+        );
+        results.set_time_shares("busy_tables", &self.busy_tables);
+
+        results
+    }
+
+    /// Records the levels of the resources after a process was resumed
+    fn update_levels(&mut self, time: SimulationTime) {
+        let setup = &self.setup;
+
+        self.busy_tables
+            .update(time, self.scheduler.in_use(setup.tables) as f64);
+
+        self.free_workers.update(
+            time,
+            (setup.config.workers - self.scheduler.in_use(setup.waiters)) as f64,
+        );
+
+        // unlimited stations are never busy
+        let limited = setup
+            .stations
+            .iter()
+            .filter(|&&station| self.scheduler.capacity(station) < u32::MAX)
+            .collect::<Vec<_>>();
+        if !limited.is_empty() {
+            let busy = limited
+                .iter()
+                .map(|&&station| {
+                    self.scheduler.in_use(station) as f64 / self.scheduler.capacity(station) as f64
+                })
+                .sum::<f64>();
+            self.kitchen_utilisation
+                .update(time, busy / limited.len() as f64);
+        }
+
+        let queue = setup
+            .stations
+            .iter()
+            .map(|&station| self.scheduler.queue_len(station))
+            .sum::<usize>();
+        self.order_queue.update(time, queue as f64);
+    }
+}
+
+/// Brings the groups of clients to the cafe
+async fn arrivals(ctx: Context<CafeState>, setup: Rc<Setup>) {
+    let mut last = None;
+
+    loop {
+        let Some(time) = ctx.state().next_arrival(last) else {
+            return;
+        };
+        ctx.hold(time - ctx.now()).await;
+        last = Some(time);
+
+        let batch = ctx.state().batch_size();
+        for _ in 0..batch {
+            ctx.spawn(client(ctx.clone(), setup.clone()));
+        }
+    }
+}
+
+/// One group of clients from the arrival to the departure
+async fn client(ctx: Context<CafeState>, setup: Rc<Setup>) {
+    let behaviour = &setup.config.client_behaviour;
+
+    if !ctx.try_request(setup.tables) {
+        ctx.state().immediately_left_clients += 1;
+        return;
+    }
+
+    let patience = ctx.state().sample(&behaviour.patience);
+    if !give_order(&ctx, &setup, patience).await {
+        ctx.state().not_dispatched_clients += 1;
+        ctx.release(setup.tables);
+        return;
+    }
+
+    loop {
+        cook_order(&ctx, &setup).await;
+
+        ctx.request(setup.waiters).await;
+        let delivery_time = ctx.state().sample(&setup.config.delivery_time);
+        ctx.hold(delivery_time).await;
+        ctx.release(setup.waiters);
+
+        let consumption_time = ctx.state().sample(&setup.config.consumption_time);
+        {
+            let mut state = ctx.state();
+            state.consumption_time.observe(consumption_time);
+        }
+        ctx.hold(consumption_time).await;
+
+        let wants_more = ctx.state().rng.gen_bool(behaviour.reorder_probability);
+        if !wants_more {
+            break;
+        }
+
+        let patience = ctx.state().sample(&behaviour.reorder_patience);
+        if !give_order(&ctx, &setup, patience).await {
+            // leaves without asking for the bill
+            ctx.release(setup.tables);
+            return;
+        }
+    }
+
+    ctx.request(setup.waiters).await;
+    let payment_time = ctx.state().sample(&setup.config.payment_time);
+    ctx.hold(payment_time).await;
+    ctx.release(setup.waiters);
+
+    ctx.release(setup.tables);
+    ctx.state().dispatched_clients += 1;
+}
+
+/// Waits for a waiter who takes the order, `false` if the patience runs out first
+async fn give_order(ctx: &Context<CafeState>, setup: &Setup, patience: SimulationTime) -> bool {
+    let waiting_since = ctx.now();
+    let served = ctx.request_within(setup.waiters, patience).await;
+    {
+        let mut state = ctx.state();
+        state.worker_waiting_time.observe(ctx.now() - waiting_since);
+    }
+
+    if !served {
+        return false;
+    }
+
+    let dancing_time = ctx.state().sample(&setup.config.dancing_time);
+    ctx.hold(dancing_time).await;
+    ctx.release(setup.waiters);
+
+    true
+}
+
+/// Every dish is cooked by its own process, the order is ready with the slowest one
+async fn cook_order(ctx: &Context<CafeState>, setup: &Setup) {
+    let config = &setup.config;
+    let client = ctx.process();
+
+    let dishes = {
+        let mut state = ctx.state();
+        let state = &mut *state;
+
+        if config.menu.is_empty() {
+            vec![(setup.stations[0], state.sample(&config.production_time))]
+        } else {
+            (0..config.menu.dishes_count(&mut state.rng))
+                .map(|_| {
+                    let dish = &config.menu.dishes[config.menu.pick(&mut state.rng)];
+                    let station = config.kitchen.station_index(dish.station.as_deref());
+                    (
+                        setup.stations[station],
+                        dish.production_time.sample(&mut state.rng),
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+
+    ctx.state()
+        .pending_dishes
+        .insert(client, dishes.len() as u32);
+    for (station, production_time) in dishes {
+        ctx.spawn(cook(ctx.clone(), station, production_time, client));
+    }
+
+    ctx.wait_until(move |state| state.pending_dishes[&client] == 0)
+        .await;
+    ctx.state().pending_dishes.remove(&client);
+}
+
+async fn cook(
+    ctx: Context<CafeState>,
+    station: ResourceId,
+    production_time: SimulationTime,
+    client: ProcessId,
+) {
+    ctx.request(station).await;
+    {
+        let mut state = ctx.state();
+        state.order_time.observe(production_time);
+    }
+
+    ctx.hold(production_time).await;
+    ctx.release(station);
+
+    *ctx.state().pending_dishes.get_mut(&client).unwrap() -= 1;
+}
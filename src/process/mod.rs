@@ -0,0 +1,406 @@
+//! Process-oriented modelling: a client or a worker is written as one sequential `async` process,
+//! which holds for some time, requests and releases resources and waits for conditions.
+//! The scheduler turns every wait into a calendar event and resumes the process when it comes.
+
+pub mod cafe;
+
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    task::{Context as TaskContext, Poll, Wake, Waker},
+};
+
+use crate::{Calendar, SimulationTime};
+
+pub type ProcessId = u32;
+pub type ResourceId = usize;
+
+/// Resumes a process within one of its waits, wakeups of finished waits are skipped
+#[derive(Debug, Clone, Copy)]
+pub struct Wakeup {
+    pub process: ProcessId,
+    token: u64,
+}
+
+type Process = Pin<Box<dyn Future<Output = ()>>>;
+type Condition<S> = Rc<dyn Fn(&S) -> bool>;
+
+/// Units which are taken by one process at a time, waiting processes get them in FIFO order
+struct Resource {
+    capacity: u32,
+    in_use: u32,
+    waiters: VecDeque<ProcessId>,
+}
+
+/// Bookkeeping shared by the scheduler and the processes
+struct Kernel<S> {
+    now: SimulationTime,
+    /// process being resumed
+    current: ProcessId,
+    next_process: ProcessId,
+    /// token of the current wait of every live process
+    tokens: HashMap<ProcessId, u64>,
+    next_token: u64,
+    /// wakeups made while a process runs, moved to the calendar after it yields
+    scheduled: Vec<(SimulationTime, Wakeup)>,
+    spawned: Vec<(ProcessId, Process)>,
+    resources: Vec<Resource>,
+    /// waiting processes a released unit was handed over to
+    granted: HashSet<ProcessId>,
+    conditions: Vec<(Wakeup, Condition<S>)>,
+}
+
+impl<S> Kernel<S> {
+    /// Starts a new wait of the current process, wakeups of its previous waits become stale
+    fn wait(&mut self) -> Wakeup {
+        let token = self.next_token;
+        self.next_token += 1;
+        self.tokens.insert(self.current, token);
+
+        Wakeup {
+            process: self.current,
+            token,
+        }
+    }
+
+    /// Wakes the process now within its current wait
+    fn wake(&mut self, process: ProcessId) {
+        if let Some(&token) = self.tokens.get(&process) {
+            self.scheduled.push((self.now, Wakeup { process, token }));
+        }
+    }
+
+    fn try_take(&mut self, resource: ResourceId) -> bool {
+        let resource = &mut self.resources[resource];
+        if resource.in_use < resource.capacity {
+            resource.in_use += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Gives control back to the scheduler once, the process goes on when it is woken
+#[derive(Default)]
+struct Suspend {
+    suspended: bool,
+}
+
+impl Future for Suspend {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<()> {
+        if self.suspended {
+            Poll::Ready(())
+        } else {
+            self.suspended = true;
+            Poll::Pending
+        }
+    }
+}
+
+/// Processes are resumed by the calendar only, so the waker has nothing to do
+struct NoopWake;
+
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Handle of a process to the simulation, the state must not be borrowed across an `await`
+pub struct Context<S> {
+    kernel: Rc<RefCell<Kernel<S>>>,
+    state: Rc<RefCell<S>>,
+}
+
+impl<S> Clone for Context<S> {
+    fn clone(&self) -> Self {
+        Self {
+            kernel: self.kernel.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S: 'static> Context<S> {
+    pub fn now(&self) -> SimulationTime {
+        self.kernel.borrow().now
+    }
+
+    /// Identifier of the running process
+    pub fn process(&self) -> ProcessId {
+        self.kernel.borrow().current
+    }
+
+    pub fn state(&self) -> RefMut<'_, S> {
+        self.state.borrow_mut()
+    }
+
+    /// Starts a process at the current time, it runs after the calling one yields
+    pub fn spawn(&self, process: impl Future<Output = ()> + 'static) -> ProcessId {
+        let mut kernel = self.kernel.borrow_mut();
+        let id = kernel.next_process;
+        kernel.next_process += 1;
+        kernel.spawned.push((id, Box::pin(process)));
+
+        id
+    }
+
+    /// Lets the time pass
+    pub async fn hold(&self, duration: SimulationTime) {
+        {
+            let mut kernel = self.kernel.borrow_mut();
+            let wakeup = kernel.wait();
+            let time = kernel.now + duration.max(0.0);
+            kernel.scheduled.push((time, wakeup));
+        }
+
+        Suspend::default().await;
+    }
+
+    /// Takes a unit of the resource, waits for one if all are in use
+    pub async fn request(&self, resource: ResourceId) {
+        self.request_within(resource, SimulationTime::INFINITY)
+            .await;
+    }
+
+    /// Takes a unit of the resource, gives up waiting for one after `timeout`.
+    /// Returns whether the unit was taken.
+    pub async fn request_within(&self, resource: ResourceId, timeout: SimulationTime) -> bool {
+        {
+            let mut kernel = self.kernel.borrow_mut();
+            if kernel.try_take(resource) {
+                return true;
+            }
+
+            let wakeup = kernel.wait();
+            let process = kernel.current;
+            kernel.resources[resource].waiters.push_back(process);
+            if timeout.is_finite() {
+                let time = kernel.now + timeout;
+                kernel.scheduled.push((time, wakeup));
+            }
+        }
+
+        Suspend::default().await;
+
+        let mut kernel = self.kernel.borrow_mut();
+        let process = kernel.current;
+        if kernel.granted.remove(&process) {
+            true
+        } else {
+            kernel.resources[resource].waiters.retain(|&p| p != process);
+            false
+        }
+    }
+
+    /// Takes a unit of the resource if one is free, never waits
+    pub fn try_request(&self, resource: ResourceId) -> bool {
+        self.kernel.borrow_mut().try_take(resource)
+    }
+
+    /// Gives a unit back, the first waiting process gets it
+    pub fn release(&self, resource: ResourceId) {
+        let mut kernel = self.kernel.borrow_mut();
+        match kernel.resources[resource].waiters.pop_front() {
+            Some(process) => {
+                kernel.granted.insert(process);
+                kernel.wake(process);
+            }
+            None => kernel.resources[resource].in_use -= 1,
+        }
+    }
+
+    /// Waits until the condition on the state holds, it is checked after every event
+    pub async fn wait_until(&self, condition: impl Fn(&S) -> bool + 'static) {
+        self.wait_until_within(condition, SimulationTime::INFINITY)
+            .await;
+    }
+
+    /// Waits until the condition holds, gives up after `timeout`. Returns whether it holds.
+    pub async fn wait_until_within(
+        &self,
+        condition: impl Fn(&S) -> bool + 'static,
+        timeout: SimulationTime,
+    ) -> bool {
+        let condition: Condition<S> = Rc::new(condition);
+        let deadline = self.now() + timeout;
+
+        loop {
+            if condition(&self.state.borrow()) {
+                return true;
+            }
+            if self.now() >= deadline {
+                return false;
+            }
+
+            {
+                let mut kernel = self.kernel.borrow_mut();
+                let wakeup = kernel.wait();
+                kernel.conditions.push((wakeup, condition.clone()));
+                if deadline.is_finite() {
+                    kernel.scheduled.push((deadline, wakeup));
+                }
+            }
+
+            Suspend::default().await;
+
+            let mut kernel = self.kernel.borrow_mut();
+            let process = kernel.current;
+            kernel
+                .conditions
+                .retain(|(wakeup, _)| wakeup.process != process);
+        }
+    }
+}
+
+/// Runs the processes, its calendar is driven by the engine like the one of an event model
+pub struct Scheduler<S> {
+    kernel: Rc<RefCell<Kernel<S>>>,
+    state: Rc<RefCell<S>>,
+    calendar: Calendar<Wakeup>,
+    processes: HashMap<ProcessId, Process>,
+}
+
+impl<S: 'static> Scheduler<S> {
+    pub fn new(state: S, events_capacity: usize) -> Self {
+        let kernel = Kernel {
+            now: 0.0,
+            current: 0,
+            next_process: 0,
+            tokens: HashMap::new(),
+            next_token: 0,
+            scheduled: Vec::new(),
+            spawned: Vec::new(),
+            resources: Vec::new(),
+            granted: HashSet::new(),
+            conditions: Vec::new(),
+        };
+
+        Self {
+            kernel: Rc::new(RefCell::new(kernel)),
+            state: Rc::new(RefCell::new(state)),
+            calendar: Calendar::with_capacity(events_capacity),
+            processes: HashMap::new(),
+        }
+    }
+
+    pub fn context(&self) -> Context<S> {
+        Context {
+            kernel: self.kernel.clone(),
+            state: self.state.clone(),
+        }
+    }
+
+    /// Adds a resource with `capacity` units, unlimited if not set
+    pub fn add_resource(&mut self, capacity: Option<u32>) -> ResourceId {
+        let mut kernel = self.kernel.borrow_mut();
+        kernel.resources.push(Resource {
+            capacity: capacity.unwrap_or(u32::MAX),
+            in_use: 0,
+            waiters: VecDeque::new(),
+        });
+
+        kernel.resources.len() - 1
+    }
+
+    pub fn in_use(&self, resource: ResourceId) -> u32 {
+        self.kernel.borrow().resources[resource].in_use
+    }
+
+    pub fn capacity(&self, resource: ResourceId) -> u32 {
+        self.kernel.borrow().resources[resource].capacity
+    }
+
+    /// Count of processes waiting for the resource
+    pub fn queue_len(&self, resource: ResourceId) -> usize {
+        self.kernel.borrow().resources[resource].waiters.len()
+    }
+
+    pub fn state(&self) -> Ref<'_, S> {
+        self.state.borrow()
+    }
+
+    pub fn state_mut(&self) -> RefMut<'_, S> {
+        self.state.borrow_mut()
+    }
+
+    pub fn spawn(&mut self, process: impl Future<Output = ()> + 'static) -> ProcessId {
+        let id = self.context().spawn(process);
+        self.settle();
+
+        id
+    }
+
+    pub fn calendar(&mut self) -> &mut Calendar<Wakeup> {
+        &mut self.calendar
+    }
+
+    /// Resumes the process the wakeup belongs to, stale wakeups are skipped
+    pub fn resume(&mut self, time: SimulationTime, wakeup: Wakeup) {
+        {
+            let mut kernel = self.kernel.borrow_mut();
+            kernel.now = time;
+            if kernel.tokens.get(&wakeup.process) != Some(&wakeup.token) {
+                return;
+            }
+        }
+
+        self.poll(wakeup.process);
+        self.settle();
+    }
+
+    fn poll(&mut self, process: ProcessId) {
+        self.kernel.borrow_mut().current = process;
+        let Some(future) = self.processes.get_mut(&process) else {
+            return;
+        };
+
+        let waker = Waker::from(Arc::new(NoopWake));
+        let mut cx = TaskContext::from_waker(&waker);
+        if future.as_mut().poll(&mut cx).is_ready() {
+            self.processes.remove(&process);
+            self.kernel.borrow_mut().tokens.remove(&process);
+        }
+    }
+
+    /// Starts the spawned processes, wakes the ones whose conditions hold
+    /// and moves the new wakeups to the calendar
+    fn settle(&mut self) {
+        loop {
+            let spawned = std::mem::take(&mut self.kernel.borrow_mut().spawned);
+            if spawned.is_empty() {
+                break;
+            }
+
+            for (id, process) in spawned {
+                self.processes.insert(id, process);
+                self.poll(id);
+            }
+        }
+
+        {
+            let state = self.state.borrow();
+            let mut kernel = self.kernel.borrow_mut();
+            let kernel = &mut *kernel;
+            let now = kernel.now;
+            kernel.conditions.retain(|(wakeup, condition)| {
+                if condition(&state) {
+                    kernel.scheduled.push((now, *wakeup));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        let scheduled = std::mem::take(&mut self.kernel.borrow_mut().scheduled);
+        for (time, wakeup) in scheduled {
+            self.calendar.schedule(time, wakeup);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{hall::Seating, Distribution, SimulationTime};
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Reservation {
+    pub time: SimulationTime,
+    pub group_size: u32,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ReservationBook {
+    /// tables are held from this long before the reserved time
+    pub hold_before: SimulationTime,
+    /// held tables are released if the group hasn't come this long after the reserved time
+    pub no_show_timeout: SimulationTime,
+    /// the probability that the group comes at all
+    pub show_probability: f64,
+    /// arrival time relative to the reserved time
+    pub lateness: Distribution,
+    pub entries: Vec<Reservation>,
+}
+
+impl Default for ReservationBook {
+    fn default() -> Self {
+        Self {
+            hold_before: 15.0,
+            no_show_timeout: 15.0,
+            show_probability: 0.9,
+            lateness: Distribution::constant(0.0),
+            entries: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum ReservationState {
+    #[default]
+    Pending,
+    Held(Seating),
+    /// the group has come
+    Claimed,
+    /// tables were released after the no-show timeout
+    Released,
+}
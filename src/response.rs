@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+/// How the values of a response are collected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseKind {
+    /// one value per observation, e.g. the waiting time of every client or a count
+    Discrete,
+    /// weighted by the time it was held, e.g. the count of busy tables
+    Continuous,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ResponseInfo {
+    pub key: String,
+    pub unit: String,
+    pub kind: ResponseKind,
+}
+
+/// Responses a model declares, in a stable order
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    responses: Vec<ResponseInfo>,
+    index: HashMap<String, usize>,
+}
+
+impl Registry {
+    pub fn declare(&mut self, key: impl Into<String>, unit: &str, kind: ResponseKind) {
+        let key = key.into();
+        assert!(
+            !self.index.contains_key(&key),
+            "Response {key} is declared twice"
+        );
+
+        self.index.insert(key.clone(), self.responses.len());
+        self.responses.push(ResponseInfo {
+            key,
+            unit: unit.to_string(),
+            kind,
+        });
+    }
+
+    /// The response with its variance, min and max within a run
+    pub fn declare_with_spread(&mut self, key: &str, unit: &str, kind: ResponseKind) {
+        self.declare(key, unit, kind);
+        self.declare(format!("{key}.variance"), &format!("{unit}²"), kind);
+        self.declare(format!("{key}.min"), unit, kind);
+        self.declare(format!("{key}.max"), unit, kind);
+    }
+
+    /// Quantiles of an observed time and the share of observations within its target
+    pub fn declare_service_level(&mut self, key: &str, unit: &str) {
+        for quantile in ["p50", "p90", "p95", "p99"] {
+            self.declare(format!("{key}.{quantile}"), unit, ResponseKind::Discrete);
+        }
+        self.declare(
+            format!("{key}.within_target"),
+            "share",
+            ResponseKind::Discrete,
+        );
+    }
+
+    /// Share of the time spent at each level `0..=max_level` of a counted quantity
+    pub fn declare_time_shares(&mut self, key: &str, max_level: usize) {
+        for level in 0..=max_level {
+            self.declare(
+                format!("{key}_{level}.time_share"),
+                "share",
+                ResponseKind::Continuous,
+            );
+        }
+    }
+
+    pub fn index_of(&self, key: &str) -> Option<usize> {
+        self.index.get(key).copied()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ResponseInfo> {
+        self.responses.iter()
+    }
+
+    /// Registry of the selected responses in their order, all of them if none are selected.
+    /// Undeclared keys are left out.
+    pub fn subset(&self, selected: &[String]) -> Registry {
+        if selected.is_empty() {
+            return self.clone();
+        }
+
+        let mut registry = Registry::default();
+        for index in selected.iter().filter_map(|key| self.index_of(key)) {
+            let info = &self.responses[index];
+            if registry.index_of(&info.key).is_none() {
+                registry.declare(info.key.clone(), &info.unit, info.kind);
+            }
+        }
+        registry
+    }
+
+    /// The selected responses, all of them if none are selected.
+    /// Undeclared keys are skipped with a warning.
+    pub fn select(&self, selected: &[String]) -> Vec<&ResponseInfo> {
+        if selected.is_empty() {
+            return self.responses.iter().collect();
+        }
+
+        selected
+            .iter()
+            .filter_map(|key| {
+                let info = self.index_of(key).map(|index| &self.responses[index]);
+                if info.is_none() {
+                    log::warn!("Unknown response {key} is skipped");
+                }
+                info
+            })
+            .collect()
+    }
+}
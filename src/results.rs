@@ -1,66 +1,222 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
 
-#[derive(Default, Clone, serde::Serialize)]
+use serde::ser::SerializeMap;
+
+use crate::{
+    accumulator::{QuantileStat, TallyStat, TimeWeightedStat},
+    response::Registry,
+    Responses,
+};
+
+/// Values of the responses declared in the registry, by key.
+/// A run may have no data for a response, e.g. no client was served;
+/// such runs are left out of the averages and counted instead.
+/// The default is empty and takes the layout of the first results added to it.
+#[derive(Default, Clone)]
 pub struct Results {
-    pub average_worker_waiting_time: f32,
-    pub average_order_time: f32,
-    pub average_busy_tables: f32,
-    pub average_free_workers: f32,
-    pub average_consumption_time: f32,
-    pub dispatched_clients: f32,
-    pub not_dispatched_clients: f32,
-    pub immediately_left_clients_count: f32,
+    registry: Arc<Registry>,
+    /// in the order of the registry, zero without data
+    values: Vec<f32>,
+    /// added runs which had data for the response
+    observed: Vec<usize>,
+    runs: usize,
+    /// responses which are set, all of them if `None`
+    selected: Option<Arc<Registry>>,
 }
 
 impl Results {
+    /// Results of one run, without data for any response yet
+    pub fn new(registry: Arc<Registry>) -> Self {
+        let len = registry.iter().len();
+
+        Self {
+            registry,
+            values: vec![0.0; len],
+            observed: vec![0; len],
+            runs: 1,
+            selected: None,
+        }
+    }
+
+    /// Results of one run where only the `selected` responses are set, see `Registry::subset`
+    pub fn of_selected(registry: Arc<Registry>, selected: Arc<Registry>) -> Self {
+        Self {
+            selected: Some(selected),
+            ..Self::new(registry)
+        }
+    }
+
+    /// Whether a response starting with `prefix` is selected, the rest is not worth computing
+    pub fn wants(&self, prefix: &str) -> bool {
+        self.selected
+            .as_ref()
+            .is_none_or(|selected| selected.iter().any(|info| info.key.starts_with(prefix)))
+    }
+
     pub fn zeros() -> Self {
         Self::default()
     }
 
-    pub fn add_mut(&mut self, other: Self) {
-        self.average_worker_waiting_time += other.average_worker_waiting_time;
-        self.average_consumption_time += other.average_consumption_time;
-        self.average_order_time += other.average_order_time;
-        self.average_busy_tables += other.average_busy_tables;
-        self.average_free_workers += other.average_free_workers;
-        self.dispatched_clients += other.dispatched_clients;
-        self.not_dispatched_clients += other.not_dispatched_clients;
-        self.immediately_left_clients_count +=
-            other.immediately_left_clients_count;
-    }
-
-    pub fn norm_mut(&mut self, count: usize) {
-        self.average_worker_waiting_time /= count as f32;
-        self.average_consumption_time /= count as f32;
-        self.average_order_time /= count as f32;
-        self.average_busy_tables /= count as f32;
-        self.average_free_workers /= count as f32;
-        self.dispatched_clients /= count as f32;
-        self.not_dispatched_clients /= count as f32;
-        self.immediately_left_clients_count /= count as f32;
+    /// `None` and non-finite values mean there is no data
+    pub fn set(&mut self, key: &str, value: impl Into<Option<f32>>) {
+        let index = self
+            .registry
+            .index_of(key)
+            .unwrap_or_else(|| panic!("Response {key} is not declared"));
+        if self
+            .selected
+            .as_ref()
+            .is_some_and(|selected| selected.index_of(key).is_none())
+        {
+            return;
+        }
+
+        match value.into().filter(|value| value.is_finite()) {
+            Some(value) => {
+                self.values[index] = value;
+                self.observed[index] = 1;
+            }
+            None => {
+                self.values[index] = 0.0;
+                self.observed[index] = 0;
+            }
+        }
+    }
+
+    /// The mean of the observations with their spread, see `Registry::declare_with_spread`
+    pub fn set_tally(&mut self, key: &str, stat: &TallyStat) {
+        if !self.wants(key) {
+            return;
+        }
+        self.set(key, stat.mean());
+        self.set_spread(key, stat.variance(), stat.min(), stat.max());
+    }
+
+    /// The time-weighted mean with its spread, see `Registry::declare_with_spread`
+    pub fn set_time_weighted(&mut self, key: &str, stat: &TimeWeightedStat) {
+        if !self.wants(key) {
+            return;
+        }
+        self.set(key, stat.mean());
+        self.set_spread(
+            key,
+            Some(stat.variance()),
+            Some(stat.min()),
+            Some(stat.max()),
+        );
+    }
+
+    /// See `Registry::declare_service_level`
+    pub fn set_service_level(&mut self, key: &str, stat: &QuantileStat) {
+        if !self.wants(&format!("{key}.")) {
+            return;
+        }
+        let [p50, p90, p95, p99] = stat.quantiles();
+        self.set(&format!("{key}.p50"), p50);
+        self.set(&format!("{key}.p90"), p90);
+        self.set(&format!("{key}.p95"), p95);
+        self.set(&format!("{key}.p99"), p99);
+        self.set(&format!("{key}.within_target"), stat.share_within());
+    }
+
+    /// Share of the time at every declared level, see `Registry::declare_time_shares`.
+    /// The levels the run never reached are held for no time.
+    pub fn set_time_shares(&mut self, key: &str, stat: &TimeWeightedStat) {
+        if !self.wants(&format!("{key}_")) {
+            return;
+        }
+        let shares = stat.time_shares();
+        for level in 0.. {
+            let level_key = format!("{key}_{level}.time_share");
+            if self.registry.index_of(&level_key).is_none() {
+                break;
+            }
+            self.set(&level_key, shares.get(level).copied().unwrap_or(0.0));
+        }
+    }
+
+    fn set_spread(&mut self, key: &str, variance: Option<f32>, min: Option<f32>, max: Option<f32>) {
+        self.set(&format!("{key}.variance"), variance);
+        self.set(&format!("{key}.min"), min);
+        self.set(&format!("{key}.max"), max);
+    }
+}
+
+impl Responses for Results {
+    fn add_mut(&mut self, other: Self) {
+        if self.values.is_empty() {
+            *self = other;
+            return;
+        }
+        assert_eq!(
+            self.values.len(),
+            other.values.len(),
+            "Results of different models are added"
+        );
+
+        for (value, other) in self.values.iter_mut().zip(other.values) {
+            *value += other;
+        }
+        for (observed, other) in self.observed.iter_mut().zip(other.observed) {
+            *observed += other;
+        }
+        self.runs += other.runs;
+    }
+
+    /// Averages over the runs which had data
+    fn norm_mut(&mut self, count: usize) {
+        debug_assert_eq!(count, self.runs, "Normalised by a wrong count of runs");
+
+        for (value, &observed) in self.values.iter_mut().zip(self.observed.iter()) {
+            if observed > 0 {
+                *value /= observed as f32;
+            }
+        }
+    }
+
+    fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    fn get(&self, key: &str) -> Option<f32> {
+        self.registry
+            .index_of(key)
+            .filter(|&index| self.observed[index] > 0)
+            .map(|index| self.values[index])
+    }
+
+    fn empty_runs(&self, key: &str) -> usize {
+        self.registry
+            .index_of(key)
+            .map_or(self.runs, |index| self.runs - self.observed[index])
+    }
+}
+
+/// Responses without data are left out
+impl serde::Serialize for Results {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for info in self.registry.iter() {
+            if let Some(value) = self.get(&info.key) {
+                map.serialize_entry(&info.key, &value)?;
+            }
+        }
+        map.end()
     }
 }
 
 impl Display for Results {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "
-            Average order time {}\n
-            Average worker waiting time {}\n
-            Average busy tables {}\n
-            Average free workers {}\n
-            Dispatched clients' count {}\n
-            Not dispatched clients' count {}\n
-            Immediately left clients' count {}\n
-            ",
-            self.average_order_time,
-            self.average_worker_waiting_time,
-            self.average_busy_tables,
-            self.average_free_workers,
-            self.dispatched_clients,
-            self.not_dispatched_clients,
-            self.immediately_left_clients_count
-        )
+        for info in self.registry.iter() {
+            match self.get(&info.key) {
+                Some(value) => writeln!(f, "{}: {value} {}", info.key, info.unit)?,
+                None => writeln!(f, "{}: no data", info.key)?,
+            }
+        }
+
+        Ok(())
     }
 }
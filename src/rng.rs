@@ -0,0 +1,33 @@
+use rand::prelude::*;
+
+pub type SimRng = StdRng;
+
+#[derive(serde::Serialize)]
+struct SeedRecord {
+    seed: u64,
+}
+
+/// Derives the seed of an independent sub-stream (one per replication) from the master seed.
+/// Uses the SplitMix64 mixer, so neighbouring stream indices produce unrelated seeds.
+pub fn stream_seed(master: u64, stream: u64) -> u64 {
+    let mut z = master.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn stream_rng(master: u64, stream: u64) -> SimRng {
+    SimRng::seed_from_u64(stream_seed(master, stream))
+}
+
+/// Returns the configured master seed or draws a fresh one, so every run has a seed to report
+pub fn resolve_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| thread_rng().random())
+}
+
+pub fn save_seed(dir: &str, seed: u64) -> std::io::Result<()> {
+    std::fs::write(
+        format!("{dir}/seed.toml"),
+        toml::to_string(&SeedRecord { seed }).unwrap(),
+    )
+}
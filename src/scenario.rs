@@ -1,182 +1,97 @@
-use std::{fmt::Display, ops::Range};
+use std::ops::Range;
 
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::{
     chart::{HyperPlane, Linear},
-    EstimationConfig, Results, Simulation, SimulationConfig,
+    response::ResponseInfo,
+    rng, Engine, ExperimentConfig, Model, ModelConfig, Responses,
 };
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum ParameterKind {
-    Workers,
-    Tables,
-    Clients,
-    Dancing,
-    Production,
-}
-
-impl Display for ParameterKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct ScenarioParameter {
-    pub kind: ParameterKind,
+pub struct ScenarioParameter<P> {
+    pub kind: P,
     pub values: Range<u32>,
     pub step: u32,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct ScenarioConfig {
-    pub parameters: Vec<ScenarioParameter>,
+pub struct ScenarioConfig<P> {
+    pub parameters: Vec<ScenarioParameter<P>>,
+    /// keys of the charted responses, all the declared ones if empty
+    #[serde(default)]
+    pub responses: Vec<String>,
 }
 
-pub fn run(config: EstimationConfig, task_name: &str) {
-    let scenario = config.scenario.clone().unwrap();
+pub fn run<M: Model>(
+    model_config: M::Config,
+    scenario: &ScenarioConfig<<M::Config as ModelConfig>::Parameter>,
+    experiment: &ExperimentConfig,
+    task_name: &str,
+) {
     assert!(scenario.parameters.len() <= 2);
 
+    // every level reuses the same sub-streams (common random numbers)
+    let seed = rng::resolve_seed(model_config.seed());
+
     if scenario.parameters.len() == 1 {
         let mut scenario_results = vec![];
-        let parameter = scenario.parameters[0].clone();
-        let build_config = config_builder(config.simulation.clone(), &parameter);
+        let parameter = &scenario.parameters[0];
+        let build_config = config_builder(model_config, parameter);
 
         for v in parameter.values.clone().step_by(parameter.step as usize) {
             let simulation_config = build_config(v);
-
-            let mut total_results = Results::zeros();
-            let mut results = Vec::<Results>::new();
-            let tmp = (0..config.experiment.total)
-                .into_par_iter()
-                .map(|_| {
-                    let mut sim = Simulation::with_config(simulation_config.clone());
-                    sim.run()
-                })
-                .collect::<Vec<_>>();
-
-            tmp.into_iter().for_each(|(run_result, _log)| {
-                total_results.add_mut(run_result.clone());
-                results.push(run_result);
-            });
-
-            total_results.norm_mut(config.experiment.total);
-
-            scenario_results.push(total_results);
+            scenario_results.push(average_of::<M>(&simulation_config, seed, experiment.total));
         }
 
         let parameters = parameter
             .values
+            .clone()
             .step_by(parameter.step as usize)
             .map(|v| v as f32)
             .collect::<Vec<f32>>();
 
-        Linear::from_data(
-            "Dispatched clients from param",
-            parameters.clone(),
-            scenario_results
-                .iter()
-                .map(|r| r.dispatched_clients)
-                .collect(),
-        )
-        .use_approximation(true)
-        .save(&format!("stats/{task_name}/DispatchedClients"))
-        .unwrap();
-
-        // Linear::from_data(
-        //     "Avg worker waiting time",
-        //     parameters.clone(),
-        //     scenario_results
-        //         .iter()
-        //         .map(|r| r.average_worker_waiting_time)
-        //         .collect(),
-        // )
-        // .use_approximation(true)
-        // .save(&format!("stats/{task_name}/WorkerWaitingTime"))
-        // .unwrap();
-
-        Linear::from_data(
-            "Not Dispatched clients from param",
-            parameters.clone(),
-            scenario_results
-                .iter()
-                .map(|r| r.not_dispatched_clients)
-                .collect(),
-        )
-        .use_approximation(true)
-        .save(&format!("stats/{task_name}/NotDispatchedClients"))
-        .unwrap();
-
-        Linear::from_data(
-            "Busy Tables from param",
-            parameters.clone(),
-            scenario_results
-                .iter()
-                .map(|r| r.average_busy_tables)
-                .collect(),
-        )
-        .use_approximation(true)
-        .save(&format!("stats/{task_name}/BusyTables"))
-        .unwrap();
-
-        Linear::from_data(
-            "Free Worker from param",
-            parameters.clone(),
-            scenario_results
-                .iter()
-                .map(|r| r.average_free_workers)
-                .collect(),
-        )
-        .use_approximation(true)
-        .save(&format!("stats/{task_name}/FreeWorkers"))
-        .unwrap();
-
-        Linear::from_data(
-            "Waiting Time from param",
-            parameters.clone(),
-            scenario_results
+        let registry = scenario_results[0].registry();
+        let selected = registry.select(&scenario.responses);
+        warn_empty_runs(&scenario_results, &selected, experiment.total);
+
+        for info in selected {
+            let key = &info.key;
+            // levels without data in any run are left out
+            let (levels, values) = parameters
                 .iter()
-                .map(|r| r.average_worker_waiting_time)
-                .collect(),
-        )
-        .use_approximation(true)
-        .save(&format!("stats/{task_name}/WaitingTime"))
-        .unwrap();
+                .zip(scenario_results.iter())
+                .filter_map(|(&level, r)| Some((level, r.get(key)?)))
+                .unzip();
+
+            Linear::from_data(
+                &format!("{key} from {}, {}", parameter.kind, info.unit),
+                levels,
+                values,
+            )
+            .use_approximation(true)
+            .save(&format!("stats/{task_name}/{key}"))
+            .unwrap();
+        }
+
+        rng::save_seed(&format!("stats/{task_name}"), seed).unwrap();
     } else {
         assert!(scenario.parameters.len() == 2);
 
-        let x_param = scenario.parameters[0].clone();
-        let z_param = scenario.parameters[1].clone();
+        let x_param = &scenario.parameters[0];
+        let z_param = &scenario.parameters[1];
 
-        let mut scenario_results = Vec::<Results>::new();
+        let mut scenario_results = Vec::<M::Responses>::new();
 
-        let build_config_with_x = config_builder(config.simulation.clone(), &x_param);
+        let build_config_with_x = config_builder(model_config, x_param);
 
         for x in x_param.values.clone().step_by(x_param.step as usize) {
             let base_config = build_config_with_x(x);
-            let build_config_with_z = config_builder(base_config, &z_param);
+            let build_config_with_z = config_builder(base_config, z_param);
 
             for z in z_param.values.clone().step_by(z_param.step as usize) {
                 let simulation_config = build_config_with_z(z);
-                let mut avg_sim_results = Results::zeros();
-
-                let tmp = (0..config.experiment.total)
-                    .into_par_iter()
-                    .map(|_| {
-                        let mut sim = Simulation::with_config(simulation_config.clone());
-                        sim.run()
-                    })
-                    .collect::<Vec<_>>();
-
-                for (run_result, _log) in tmp.into_iter() {
-                    avg_sim_results.add_mut(run_result.clone());
-                }
-
-                avg_sim_results.norm_mut(config.experiment.total);
-
-                scenario_results.push(avg_sim_results);
+                scenario_results.push(average_of::<M>(&simulation_config, seed, experiment.total));
             }
         }
 
@@ -194,57 +109,94 @@ pub fn run(config: EstimationConfig, task_name: &str) {
             .map(|v| v as f64)
             .collect::<Vec<_>>();
 
-        HyperPlane::from_data(
-            x_values.clone(),
-            z_values.clone(),
-            scenario_results
-                .iter()
-                .map(|r| r.average_worker_waiting_time as f64)
-                .collect(),
-            &format!("WaitingTime over X={} Z={}", x_param.kind, z_param.kind),
-        )
-        .save("stats/multi/WaitingTime")
-        .unwrap();
-
-        HyperPlane::from_data(
-            x_values.clone(),
-            z_values.clone(),
-            scenario_results
+        let registry = scenario_results[0].registry();
+        let selected = registry.select(&scenario.responses);
+        warn_empty_runs(&scenario_results, &selected, experiment.total);
+
+        for info in selected {
+            let key = &info.key;
+            // the surface needs every level, it is skipped if one has no data
+            let Some(values) = scenario_results
                 .iter()
-                .map(|r| r.average_free_workers as f64)
-                .collect(),
-            &format!("FreeWorkers over X={} Z={}", x_param.kind, z_param.kind),
-        )
-        .save("stats/multi/FreeWorkers")
-        .unwrap();
+                .map(|r| r.get(key).map(f64::from))
+                .collect::<Option<Vec<_>>>()
+            else {
+                log::warn!("Some levels had no data for {key}, its surface is skipped");
+                continue;
+            };
+
+            HyperPlane::from_data(
+                x_values.clone(),
+                z_values.clone(),
+                values,
+                &format!(
+                    "{key} over X={} Z={}, {}",
+                    x_param.kind, z_param.kind, info.unit
+                ),
+            )
+            .save(&format!("stats/multi/{key}"))
+            .unwrap();
+        }
+
+        rng::save_seed("stats/multi", seed).unwrap();
+    }
+}
+
+/// Sums up the runs without data for the charted responses over all levels
+fn warn_empty_runs<R: Responses>(results: &[R], selected: &[&ResponseInfo], total: usize) {
+    for info in selected {
+        let empty = results
+            .iter()
+            .map(|r| r.empty_runs(&info.key))
+            .sum::<usize>();
+        if empty == 0 {
+            continue;
+        }
+
+        let empty_levels = results
+            .iter()
+            .filter(|r| r.get(&info.key).is_none())
+            .count();
+        log::warn!(
+            "{empty} of {} runs had no data for {}, {empty_levels} of {} levels had none",
+            total * results.len(),
+            info.key,
+            results.len()
+        );
+    }
+}
+
+/// Average responses of `total` replications
+fn average_of<M: Model>(config: &M::Config, seed: u64, total: usize) -> M::Responses {
+    let mut total_results = M::Responses::default();
+
+    let tmp = (0..total)
+        .into_par_iter()
+        .map(|i| {
+            let mut sim = Engine::<M>::with_stream(config.clone(), seed, i as u64);
+            sim.run()
+        })
+        .collect::<Vec<_>>();
+
+    for (run_result, _log) in tmp.into_iter() {
+        total_results.add_mut(run_result);
     }
+
+    total_results.norm_mut(total);
+
+    total_results
 }
 
-fn config_builder(
-    mut base_config: SimulationConfig,
-    parameter: &ScenarioParameter,
-) -> impl Fn(u32) -> SimulationConfig {
+fn config_builder<C: ModelConfig>(
+    mut base_config: C,
+    parameter: &ScenarioParameter<C::Parameter>,
+) -> impl Fn(u32) -> C {
     let kind = parameter.kind;
-    base_config.use_logs = false;
+    base_config.set_use_logs(false);
 
     move |v| {
         let mut config = base_config.clone();
-
-        match kind {
-            ParameterKind::Workers => {
-                config.workers = v;
-            }
-            ParameterKind::Tables => {
-                config.tables = v;
-            }
-            ParameterKind::Clients => config.client_ratio = v as f64 / 100.0,
-            ParameterKind::Dancing => {
-                config.dancing_time = 1..v;
-            }
-            ParameterKind::Production => {
-                config.production_time = 1..v;
-            }
-        }
+        config.set_parameter(kind, v);
 
         config
     }
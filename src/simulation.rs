@@ -1,268 +1,1113 @@
-use std::collections::VecDeque;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
 
 use rand::prelude::*;
 
-use crate::{Event, Log, Results, SimulationConfig};
+use crate::{
+    accumulator::{QuantileStat, TallyStat, TimeWeightedStat},
+    arrival::Arrivals,
+    client::ClientStage,
+    engine::{Engine, Model},
+    hall::{Hall, Seating, Table},
+    kitchen::{Kitchen, Order},
+    reservation::ReservationState,
+    response::{Registry, ResponseKind},
+    rng::SimRng,
+    staff::{DispatchPolicy, Task, TaskKind},
+    trace::{Levels, TraceEvent, Tracer},
+    Calendar, Client, ClientClass, DepartureReason, EntityId, Event, EventKind, Log, Results,
+    SimulationConfig, SimulationTime,
+};
 
-pub struct Simulation {
-    t_max_time: u32,
-
-    available_tables: u32,
+/// The cafe as a discrete-event model
+pub struct Cafe {
+    hall: Hall,
+    kitchen: Kitchen,
+    /// dishes being cooked by their ticket
+    cooking: HashMap<u32, Order>,
+    next_ticket: u32,
+    /// dishes of the current order of the client which are not cooked yet
+    pending_dishes: HashMap<EntityId, u32>,
+    /// time the current order of the client was taken, until the food is delivered
+    food_ordered: HashMap<EntityId, SimulationTime>,
+    /// kitchen station of every dish of the menu
+    dish_stations: Vec<usize>,
     available_workers: u32,
+    /// workers busy with every kind of task
+    busy_workers: [u32; TaskKind::COUNT],
+    /// scheduled count of workers
+    on_duty: u32,
+    /// busy workers whose shift is over, they leave after the current task
+    leaving_workers: u32,
+
+    calendar: Calendar<Event>,
+    arrivals: Arrivals,
+    /// arrivals of the current batch which are not processed yet
+    arrivals_in_batch: u32,
+    pending_tasks: VecDeque<Task>,
+    /// groups waiting for a table at the entrance
+    entrance: VecDeque<EntityId>,
+    next_entity: EntityId,
+    /// state of every entry of the reservation book
+    reservations: Vec<ReservationState>,
+    /// reservation entry of the clients who are going to come
+    reservation_of: HashMap<EntityId, usize>,
 
-    events: VecDeque<Event>,
+    clients: HashMap<EntityId, Client>,
+    finished_clients: Vec<Client>,
+    record_clients: bool,
 
-    average_worker_waiting_time: (f32, usize),
-    average_order_time: (f32, usize),
-    average_consumption_time: (f32, usize),
-    average_busy_tables: (f32, usize),
-    average_free_workers: (f32, usize),
+    worker_waiting_time: TallyStat,
+    order_time: TallyStat,
+    consumption_time: TallyStat,
+    entrance_time: TallyStat,
+    busy_tables: TimeWeightedStat,
+    busy_seats: TimeWeightedStat,
+    free_workers: TimeWeightedStat,
+    scheduled_workers: TimeWeightedStat,
+    task_workers: [TimeWeightedStat; TaskKind::COUNT],
+    kitchen_utilisation: TimeWeightedStat,
+    order_queue: TimeWeightedStat,
+    entrance_queue: TimeWeightedStat,
+    worker_waiting_service: QuantileStat,
+    food_waiting_service: QuantileStat,
+    sojourn_service: QuantileStat,
 
-    average_worker_waiting_time_pre: f32,
-    average_order_time_pre: f32,
-    average_consumption_time_pre: f32,
-    average_busy_tables_pre: f32,
-    average_free_workers_pre: f32,
+    class_arrived: [usize; ClientClass::ALL.len()],
+    class_balked: [usize; ClientClass::ALL.len()],
+    class_served: [usize; ClientClass::ALL.len()],
+    class_waiting_time: [TallyStat; ClientClass::ALL.len()],
+
+    dish_cooked: Vec<usize>,
+    dish_lead_time: Vec<TallyStat>,
 
     not_dispatched_clients: usize,
     dispatched_clients_count: usize,
+    revenue: f32,
     immediately_left_clients_count: usize,
+    entrance_reneged_clients: usize,
     // average_time_in: Vec<u32>,
+    /// structured trace of the handled events, only in the trace mode
+    tracer: Option<Tracer>,
+    registry: Arc<Registry>,
     config: SimulationConfig,
-    world_time: Option<SimulationTick>,
+    rng: SimRng,
 }
 
-pub type SimulationTick = u32;
+/// The cafe run by the generic engine
+pub type Simulation = Engine<Cafe>;
 
 impl Simulation {
-    pub fn with_config(config: SimulationConfig) -> Self {
-        Self {
-            t_max_time: config.max_time,
+    /// Same as `run`, but also returns records of the clients who departed during the run
+    pub fn run_with_clients(&mut self) -> (Results, Log, Vec<Client>) {
+        self.model_mut().record_clients = true;
+        let (results, log) = self.run();
+
+        let cafe = self.model_mut();
+        cafe.record_clients = false;
+        (results, log, std::mem::take(&mut cafe.finished_clients))
+    }
+}
 
-            available_tables: config.tables,
+impl Model for Cafe {
+    type Config = SimulationConfig;
+    type Event = Event;
+    type Responses = Results;
+
+    fn new(config: SimulationConfig, rng: SimRng) -> Self {
+        let events_capacity = config.events_capacity;
+
+        Self {
+            hall: Hall::new(config.table_capacities(), config.seating_policy),
+            kitchen: Kitchen::new(config.kitchen.clone()),
+            cooking: HashMap::new(),
+            next_ticket: 0,
+            pending_dishes: HashMap::new(),
+            food_ordered: HashMap::new(),
+            dish_stations: config
+                .menu
+                .dishes
+                .iter()
+                .map(|dish| config.kitchen.station_index(dish.station.as_deref()))
+                .collect(),
             available_workers: config.workers,
+            busy_workers: [0; TaskKind::COUNT],
+            on_duty: config.workers,
+            leaving_workers: 0,
 
-            config,
-            events: VecDeque::with_capacity(150),
-            average_worker_waiting_time: (0.0, 0),
-            average_order_time: (0.0, 0),
-            average_busy_tables: (0.0, 0),
-            average_free_workers: (0.0, 0),
-            average_consumption_time: (0.0, 0),
-
-            average_worker_waiting_time_pre: 0.0,
-            average_order_time_pre: 0.0,
-            average_consumption_time_pre: 0.0,
-            average_busy_tables_pre: 0.0,
-            average_free_workers_pre: 0.0,
+            calendar: Calendar::with_capacity(events_capacity),
+            arrivals: Arrivals::new(
+                config.arrivals.clone(),
+                config.client_ratio,
+                config.max_time,
+            ),
+            arrivals_in_batch: 0,
+            pending_tasks: VecDeque::new(),
+            entrance: VecDeque::new(),
+            next_entity: 0,
+            reservations: vec![ReservationState::Pending; config.reservations.entries.len()],
+            reservation_of: HashMap::new(),
+
+            clients: HashMap::new(),
+            finished_clients: Vec::new(),
+            record_clients: false,
+
+            worker_waiting_time: TallyStat::default(),
+            order_time: TallyStat::default(),
+            consumption_time: TallyStat::default(),
+            entrance_time: TallyStat::default(),
+            busy_tables: TimeWeightedStat::new(0.0, 0.0),
+            busy_seats: TimeWeightedStat::new(0.0, 0.0),
+            free_workers: TimeWeightedStat::new(0.0, config.workers as f64),
+            scheduled_workers: TimeWeightedStat::new(0.0, config.workers as f64),
+            task_workers: std::array::from_fn(|_| TimeWeightedStat::new(0.0, 0.0)),
+            kitchen_utilisation: TimeWeightedStat::new(0.0, 0.0),
+            order_queue: TimeWeightedStat::new(0.0, 0.0),
+            entrance_queue: TimeWeightedStat::new(0.0, 0.0),
+            worker_waiting_service: QuantileStat::new(config.service_targets.worker_waiting_time),
+            food_waiting_service: QuantileStat::new(config.service_targets.food_waiting_time),
+            sojourn_service: QuantileStat::new(config.service_targets.sojourn_time),
+
+            class_arrived: [0; ClientClass::ALL.len()],
+            class_balked: [0; ClientClass::ALL.len()],
+            class_served: [0; ClientClass::ALL.len()],
+            class_waiting_time: Default::default(),
+
+            dish_cooked: vec![0; config.menu.dishes.len()],
+            dish_lead_time: vec![TallyStat::default(); config.menu.dishes.len()],
 
             not_dispatched_clients: 0,
             dispatched_clients_count: 0,
+            revenue: 0.0,
             immediately_left_clients_count: 0,
-            world_time: None,
+            entrance_reneged_clients: 0,
+            tracer: Tracer::from_config(&config.trace),
+            registry: Arc::new(Cafe::declare_responses(&config)),
+            config,
+            rng,
         }
     }
 
-    pub fn run(&mut self) -> (Results, Log) {
-        let mut log = Log::empty();
+    fn start(&mut self) {
+        self.schedule_shifts();
+        self.schedule_reservations();
+        self.schedule_arrivals(None);
+    }
+
+    fn calendar(&mut self) -> &mut Calendar<Event> {
+        &mut self.calendar
+    }
 
-        let start_time = self.world_time.unwrap_or(0);
-        let end_time = start_time + self.t_max_time;
+    fn handle(&mut self, time: SimulationTime, event: Event) {
+        if self.tracer.is_none() {
+            self.process_event(time, event);
+            self.update_levels(time);
+            return;
+        }
 
-        self.world_time = Some(end_time);
+        let before = self.levels();
+        self.process_event(time, event);
+        self.update_levels(time);
+        let after = self.levels();
 
-        for tick in start_time..end_time {
-            log::trace!("---  Tick #{tick}  ---");
-            self.generate_new_events(tick);
-            self.process_tick(tick);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(TraceEvent {
+                time,
+                entity: event.entity,
+                kind: event.kind,
+                payload: event.payload,
+                before,
+                after,
+            });
+        }
+    }
 
-            if self.config.use_logs {
-                let part_results = self.partial_result();
-                log.append(tick - start_time, part_results);
-            }
+    /// The levels only change at events, so the time-weighted averages just catch up
+    fn observe(&mut self, time: SimulationTime) {
+        for stat in self.level_stats_mut() {
+            stat.advance(time);
         }
+    }
+
+    fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    fn responses(&self) -> Results {
+        result_of(self, Results::new(self.registry.clone()))
+    }
 
-        (result_of(self), log)
+    fn selected_responses(&self, selected: &Arc<Registry>) -> Results {
+        result_of(
+            self,
+            Results::of_selected(self.registry.clone(), selected.clone()),
+        )
     }
 
-    pub fn reset_metrics(&mut self) {
-        self.average_worker_waiting_time = (0.0, 0);
-        self.average_order_time = (0.0, 0);
-        self.average_consumption_time = (0.0, 0);
-        self.average_busy_tables = (0.0, 0);
-        self.average_free_workers = (0.0, 0);
+    fn reset_metrics(&mut self) {
+        self.worker_waiting_time = TallyStat::default();
+        self.order_time = TallyStat::default();
+        self.consumption_time = TallyStat::default();
+        self.entrance_time = TallyStat::default();
+        for stat in self.level_stats_mut() {
+            stat.reset();
+        }
+        self.worker_waiting_service.reset();
+        self.food_waiting_service.reset();
+        self.sojourn_service.reset();
 
-        self.average_worker_waiting_time_pre = 0.0;
-        self.average_order_time_pre = 0.0;
-        self.average_consumption_time_pre = 0.0;
-        self.average_busy_tables_pre = 0.0;
-        self.average_free_workers_pre = 0.0;
+        self.class_arrived = [0; ClientClass::ALL.len()];
+        self.class_balked = [0; ClientClass::ALL.len()];
+        self.class_served = [0; ClientClass::ALL.len()];
+        self.class_waiting_time = Default::default();
+
+        self.dish_cooked.fill(0);
+        self.dish_lead_time.fill(TallyStat::default());
 
         self.not_dispatched_clients = 0;
         self.dispatched_clients_count = 0;
+        self.revenue = 0.0;
         self.immediately_left_clients_count = 0;
+        self.entrance_reneged_clients = 0;
     }
+}
 
-    fn process_tick(&mut self, time: u32) {
-        let mut new_events = VecDeque::with_capacity(self.events.len());
+impl Cafe {
+    /// Responses of the cafe, the dishes and the tables depend on the config
+    pub fn declare_responses(config: &SimulationConfig) -> Registry {
+        use ResponseKind::{Continuous, Discrete};
 
-        while let Some(event) = self.events.pop_front() {
-            match event {
-                Event::Enter(arrival_time) => {
-                    log::trace!("Client enter in {arrival_time}");
+        let mut registry = Registry::default();
 
-                    if self.available_tables > 0 {
-                        self.available_tables -= 1;
+        registry.declare_with_spread("average_worker_waiting_time", "time", Discrete);
+        registry.declare_with_spread("average_order_time", "time", Discrete);
+        registry.declare_with_spread("average_consumption_time", "time", Discrete);
+        registry.declare_with_spread("average_entrance_time", "time", Discrete);
+        registry.declare_with_spread("average_busy_tables", "tables", Continuous);
+        registry.declare_with_spread("average_free_workers", "workers", Continuous);
+        registry.declare_with_spread("average_order_queue", "dishes", Continuous);
+        registry.declare_with_spread("average_entrance_queue", "groups", Continuous);
 
-                        let leave_time = thread_rng().gen_range(5..10) + time;
-                        new_events.push_back(Event::WaitingForWorker(time, leave_time, true));
-                    } else {
-                        self.immediately_left_clients_count += 1;
-                        log::trace!("Client leave immediately");
-                    }
+        registry.declare("seat_utilisation", "share", Continuous);
+        registry.declare("average_scheduled_workers", "workers", Continuous);
+        for utilisation in [
+            "worker_utilisation",
+            "order_utilisation",
+            "delivery_utilisation",
+            "payment_utilisation",
+            "kitchen_utilisation",
+        ] {
+            registry.declare(utilisation, "share", Continuous);
+        }
+
+        for count in [
+            "dispatched_clients",
+            "not_dispatched_clients",
+            "immediately_left_clients_count",
+            "entrance_reneged_clients",
+        ] {
+            registry.declare(count, "clients", Discrete);
+        }
+        for money in ["revenue", "costs", "profit"] {
+            registry.declare(money, "money", Discrete);
+        }
+
+        for class in ClientClass::ALL {
+            let class = class.name();
+            registry.declare(
+                format!("{class}.average_worker_waiting_time"),
+                "time",
+                Discrete,
+            );
+            registry.declare(format!("{class}.balking_rate"), "share", Discrete);
+            registry.declare(format!("{class}.served_clients"), "clients", Discrete);
+        }
+
+        for index in 0..config.menu.dishes.len() {
+            registry.declare(format!("dish_{index}.throughput"), "portions", Discrete);
+            registry.declare(format!("dish_{index}.lead_time"), "time", Discrete);
+        }
+
+        registry.declare_time_shares("busy_tables", config.table_capacities().len());
+
+        registry.declare_service_level("worker_waiting_time", "time");
+        registry.declare_service_level("food_waiting_time", "time");
+        registry.declare_service_level("sojourn_time", "time");
+
+        registry
+    }
+
+    /// Records the levels after the event at `time`
+    fn update_levels(&mut self, time: SimulationTime) {
+        self.busy_tables
+            .update(time, self.hall.busy_tables() as f64);
+        self.busy_seats.update(time, self.hall.seated() as f64);
+        self.free_workers
+            .update(time, self.available_workers as f64);
+        self.scheduled_workers.update(time, self.on_duty as f64);
+        for (stat, busy) in self.task_workers.iter_mut().zip(self.busy_workers) {
+            stat.update(time, busy as f64);
+        }
+        self.kitchen_utilisation
+            .update(time, self.kitchen.utilisation() as f64);
+        self.order_queue
+            .update(time, self.kitchen.queue_len() as f64);
+        self.entrance_queue.update(time, self.entrance.len() as f64);
+    }
+
+    fn level_stats_mut(&mut self) -> impl Iterator<Item = &mut TimeWeightedStat> {
+        [
+            &mut self.busy_tables,
+            &mut self.busy_seats,
+            &mut self.free_workers,
+            &mut self.scheduled_workers,
+            &mut self.kitchen_utilisation,
+            &mut self.order_queue,
+            &mut self.entrance_queue,
+        ]
+        .into_iter()
+        .chain(self.task_workers.iter_mut())
+    }
+
+    pub fn levels(&self) -> Levels {
+        Levels {
+            busy_tables: self.hall.busy_tables(),
+            free_workers: self.available_workers,
+            busy_workers: self.busy_workers.iter().sum(),
+            pending_tasks: self.pending_tasks.len(),
+            order_queue: self.kitchen.queue_len(),
+            entrance_queue: self.entrance.len(),
+        }
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        self.hall.tables()
+    }
+
+    /// Tables held for reservations whose groups have not come yet
+    pub fn held_tables(&self) -> Vec<usize> {
+        self.reservations
+            .iter()
+            .filter_map(|state| match state {
+                ReservationState::Held(seating) => Some(seating),
+                _ => None,
+            })
+            .flat_map(|seating| seating.iter().map(|(table, _)| *table))
+            .collect()
+    }
+
+    /// Groups in the cafe ordered by arrival
+    pub fn clients(&self) -> Vec<&Client> {
+        let mut clients = self.clients.values().collect::<Vec<_>>();
+        clients.sort_by_key(|client| client.id);
+
+        clients
+    }
+
+    fn process_event(&mut self, time: SimulationTime, event: Event) {
+        log::trace!("--- {time:.3}: {event} ---");
+
+        match event.kind {
+            EventKind::Arrival => {
+                log::trace!("Client enter in {time}");
+                self.arrivals_in_batch -= 1;
+                if self.arrivals_in_batch == 0 {
+                    self.schedule_arrivals(Some(time));
                 }
-                Event::WaitingForWorker(start_time, leave_time, is_first_time) => {
-                    log::trace!("Client is waiting for worker");
-                    if leave_time <= time {
-                        self.available_tables += 1;
-                        self.average_worker_waiting_time.0 += (leave_time - start_time) as f32;
-                        self.average_worker_waiting_time.1 += 1;
-
-                        if is_first_time {
-                            self.not_dispatched_clients += 1;
-                        }
 
-                        log::trace!("Client exit without worker");
-                        continue;
-                    }
+                let group_size =
+                    self.config.group_size.sample(&mut self.rng).ceil().max(1.0) as u32;
+                let vip_share = self.config.classes.vip_share;
+                let class = if vip_share > 0.0 && self.rng.gen_bool(vip_share) {
+                    ClientClass::Vip
+                } else {
+                    ClientClass::WalkIn
+                };
 
-                    if self.available_workers > 0 {
-                        log::trace!("Client communicates with worker");
+                self.arrive(event.client(), class, group_size, None, time);
+            }
 
-                        self.available_workers -= 1;
+            EventKind::ReservationArrival => {
+                let index = self.reservation_of.remove(&event.client()).unwrap();
+                let group_size = self.config.reservations.entries[index].group_size;
 
-                        let free_worker_time =
-                            thread_rng().gen_range(self.config.dancing_time.clone()) + time;
-                        // let free_worker_time = time;
-                        new_events.push_back(Event::WorkerWalkingDance(free_worker_time));
+                let held = match std::mem::replace(
+                    &mut self.reservations[index],
+                    ReservationState::Claimed,
+                ) {
+                    ReservationState::Held(seating) => Some(seating),
+                    _ => None,
+                };
 
-                        self.average_worker_waiting_time.0 += (time - start_time) as f32;
-                        self.average_worker_waiting_time.1 += 1;
-                    } else {
-                        new_events.push_back(Event::WaitingForWorker(
-                            start_time,
-                            leave_time,
-                            is_first_time,
-                        ));
+                self.arrive(
+                    event.client(),
+                    ClientClass::Reservation,
+                    group_size,
+                    held,
+                    time,
+                );
+            }
+
+            EventKind::ReservationHold => {
+                let index = event.payload() as usize;
+                if let ReservationState::Pending = self.reservations[index] {
+                    let group_size = self.config.reservations.entries[index].group_size;
+
+                    match self.hall.seat(group_size) {
+                        Some(seating) => {
+                            log::trace!("Tables are held for reservation #{index}");
+                            self.reservations[index] = ReservationState::Held(seating);
+                        }
+                        None => log::trace!("No tables to hold for reservation #{index}"),
                     }
                 }
+            }
+
+            EventKind::ReservationExpired => {
+                let index = event.payload() as usize;
+                if let ReservationState::Held(seating) = &self.reservations[index] {
+                    log::trace!("Reservation #{index} is not claimed, tables are released");
+                    self.hall.release(seating);
+                    self.reservations[index] = ReservationState::Released;
+                    self.seat_from_entrance(time);
+                }
+            }
+
+            EventKind::EntranceReneged => {
+                // the client may have got a table already
+                if let Some(position) = self.entrance.iter().position(|&e| e == event.client()) {
+                    self.entrance.remove(position);
+                    self.entrance_reneged_clients += 1;
+                    self.observe_entrance_time(event.client(), time);
+                    self.finish_client(event.client(), time, DepartureReason::Reneged);
 
-                Event::WorkerWalkingDance(free_worker_time) => {
-                    if time >= free_worker_time {
-                        log::trace!("Worker becomes free");
-                        self.available_workers += 1;
-                        assert!(self.available_workers <= self.config.workers);
-                        //todo: add correlation producing time on workload
+                    log::trace!("Client leaves the line at the entrance");
+                }
+            }
 
-                        let producing_time =
-                            thread_rng().gen_range(self.config.production_time.clone());
+            EventKind::PatienceExpired => {
+                // the client may have been served already, or be waiting again after re-order
+                let position = self.pending_tasks.iter().position(|task| {
+                    task.entity == event.client()
+                        && task.leave_time.is_some_and(|leave_time| leave_time <= time)
+                });
 
-                        self.average_order_time.0 += producing_time as f32;
-                        self.average_order_time.1 += 1;
+                if let Some(task) = position.and_then(|p| self.pending_tasks.remove(p)) {
+                    self.leave_table(task.entity, time);
+                    self.observe_waiting_time(task.entity, time - task.created);
 
-                        let finish_food_time = producing_time + time;
-                        new_events.push_back(Event::WaitingForFood(finish_food_time));
+                    let reason = if task.is_first_time {
+                        self.not_dispatched_clients += 1;
+                        DepartureReason::Reneged
                     } else {
-                        log::trace!("Worker is dancing");
-                        new_events.push_front(Event::WorkerWalkingDance(free_worker_time));
-                    }
+                        DepartureReason::Served
+                    };
+                    self.finish_client(task.entity, time, reason);
+
+                    log::trace!("Client exit without worker");
                 }
+            }
 
-                Event::WaitingForFood(finish_food_time) => {
-                    if time >= finish_food_time {
-                        log::trace!("Client starts consuming");
+            EventKind::OrderTaken => {
+                self.release_worker(TaskKind::TakeOrder);
 
-                        let consumption_time =
-                            thread_rng().gen_range(self.config.consumption_time.clone());
+                let is_reorder = self.clients[&event.client()].order_taken.is_some();
+                let revenue = if is_reorder {
+                    &self.config.economics.reorder_revenue
+                } else {
+                    &self.config.economics.order_revenue
+                }
+                .sample(&mut self.rng);
 
-                        self.average_consumption_time.0 += consumption_time as f32;
-                        self.average_consumption_time.1 += 1;
+                let revenue = revenue + self.place_order(event.client(), time);
+                self.revenue += revenue as f32;
 
-                        let end_consume_time = consumption_time + time;
+                self.food_ordered.insert(event.client(), time);
+                let client = self.client_mut(event.client());
+                client.order_taken.get_or_insert(time);
+                client.bill += revenue;
+                client.stage = ClientStage::WaitingForFood;
 
-                        new_events.push_back(Event::ConsumeFood(end_consume_time));
-                    } else {
-                        log::trace!("Client is waiting for food");
-                        new_events.push_front(Event::WaitingForFood(finish_food_time));
-                    }
+                self.start_cooking(time);
+                self.dispatch_workers(time);
+            }
+
+            EventKind::DishReady => {
+                let order = self.cooking.remove(&event.payload()).unwrap();
+                self.kitchen.finish(order.station);
+                self.start_cooking(time);
+
+                if let Some(dish) = order.dish {
+                    self.dish_cooked[dish] += 1;
+                    self.dish_lead_time[dish].observe(time - order.placed);
                 }
 
-                Event::ConsumeFood(end_consume_time) => {
-                    if time >= end_consume_time {
-                        log::trace!("Client consuming is finished");
-                        let we_want_eat_more = thread_rng().gen_bool(0.2);
-                        if we_want_eat_more {
-                            log::trace!("Client wants mo-o-ore!!!");
-                            let leave_time = thread_rng().gen_range(1..3) + time;
-                            new_events.push_back(Event::WaitingForWorker(time, leave_time, false));
-                        } else {
-                            self.dispatched_clients_count += 1;
-                            self.available_tables += 1;
-                            log::trace!("Client exit after consumption");
-                            // new_events.push_back(Event::LeavePlace);
-                        }
-                    } else {
-                        log::trace!("Client is consuming food");
-                        new_events.push_back(Event::ConsumeFood(end_consume_time));
-                    }
+                // the order is complete when its slowest dish is done
+                let remaining = self.pending_dishes.get_mut(&order.entity).unwrap();
+                *remaining -= 1;
+                if *remaining == 0 {
+                    log::trace!("Food is waiting for delivery");
+                    self.pending_dishes.remove(&order.entity);
+                    self.client_mut(order.entity).food_ready.get_or_insert(time);
+                    self.add_task(order.entity, TaskKind::DeliverFood, time);
+                }
+            }
+
+            EventKind::FoodDelivered => {
+                log::trace!("Client starts consuming");
+                self.release_worker(TaskKind::DeliverFood);
+                if let Some(ordered) = self.food_ordered.remove(&event.client()) {
+                    self.food_waiting_service.observe(time - ordered);
+                }
+                let client = self.client_mut(event.client());
+                client.consumption_start.get_or_insert(time);
+                client.stage = ClientStage::Eating;
+
+                let class = self.clients[&event.client()].class;
+                let consumption_time = self
+                    .config
+                    .classes
+                    .get(class)
+                    .consumption_time
+                    .as_ref()
+                    .unwrap_or(&self.config.consumption_time)
+                    .sample(&mut self.rng);
+
+                self.consumption_time.observe(consumption_time);
+
+                self.calendar.schedule(
+                    consumption_time + time,
+                    Event::new(event.client(), EventKind::ConsumptionFinished),
+                );
+                self.dispatch_workers(time);
+            }
+
+            EventKind::ConsumptionFinished => {
+                log::trace!("Client consuming is finished");
+                let we_want_eat_more = self
+                    .rng
+                    .gen_bool(self.config.client_behaviour.reorder_probability);
+                if we_want_eat_more {
+                    log::trace!("Client wants mo-o-ore!!!");
+                    let client = self.client_mut(event.client());
+                    client.reorders.push(time);
+                    client.stage = ClientStage::WaitingForWorker;
+                    let leave_time = self
+                        .config
+                        .client_behaviour
+                        .reorder_patience
+                        .sample(&mut self.rng)
+                        + time;
+                    self.wait_for_worker(event.client(), time, leave_time, false);
+                } else {
+                    log::trace!("Client asks for the bill");
+                    self.client_mut(event.client()).stage = ClientStage::WaitingForBill;
+                    self.add_task(event.client(), TaskKind::TakePayment, time);
                 }
             }
+
+            EventKind::PaymentTaken => {
+                self.release_worker(TaskKind::TakePayment);
+                self.dispatched_clients_count += 1;
+                self.class_served[self.clients[&event.client()].class as usize] += 1;
+                self.leave_table(event.client(), time);
+                self.finish_client(event.client(), time, DepartureReason::Served);
+                log::trace!("Client exit after consumption");
+
+                self.dispatch_workers(time);
+            }
+
+            EventKind::ShiftChange => {
+                let workers = self.config.staffing[event.payload() as usize].workers;
+                self.change_shift(workers, time);
+            }
+        }
+    }
+
+    fn arrive(
+        &mut self,
+        entity: EntityId,
+        class: ClientClass,
+        group_size: u32,
+        held: Option<Seating>,
+        time: SimulationTime,
+    ) {
+        self.class_arrived[class as usize] += 1;
+        self.clients
+            .insert(entity, Client::new(entity, class, group_size, time));
+
+        match held.or_else(|| self.hall.seat(group_size)) {
+            Some(seating) => self.take_seat(entity, seating, time),
+            None if self.entrance.len() < self.config.entrance.capacity => {
+                log::trace!("Client waits for a table at the entrance");
+                self.entrance.push_back(entity);
+
+                if let Some(reneging_time) = &self.config.entrance.reneging_time {
+                    let leave_time = reneging_time.sample(&mut self.rng) + time;
+                    self.calendar
+                        .schedule(leave_time, Event::new(entity, EventKind::EntranceReneged));
+                }
+            }
+            None => {
+                self.immediately_left_clients_count += 1;
+                self.class_balked[class as usize] += 1;
+                self.finish_client(entity, time, DepartureReason::Balked);
+                log::trace!("Client leave immediately");
+            }
+        }
+    }
+
+    fn take_seat(&mut self, entity: EntityId, seating: Seating, time: SimulationTime) {
+        let client = self.client_mut(entity);
+        client.seated = Some(time);
+        client.seating = seating;
+        client.stage = ClientStage::WaitingForWorker;
+        let class = client.class;
+
+        let leave_time = self
+            .config
+            .classes
+            .get(class)
+            .patience
+            .as_ref()
+            .unwrap_or(&self.config.client_behaviour.patience)
+            .sample(&mut self.rng)
+            + time;
+        self.wait_for_worker(entity, time, leave_time, true);
+    }
+
+    /// Seats the groups from the line which fit at the free tables, in the order of the line
+    fn seat_from_entrance(&mut self, time: SimulationTime) {
+        let mut position = 0;
+        while position < self.entrance.len() {
+            let entity = self.entrance[position];
+
+            match self.hall.seat(self.clients[&entity].group_size) {
+                Some(seating) => {
+                    self.entrance.remove(position);
+                    self.observe_entrance_time(entity, time);
+                    self.take_seat(entity, seating, time);
+                }
+                None => position += 1,
+            }
+        }
+    }
+
+    fn observe_entrance_time(&mut self, entity: EntityId, time: SimulationTime) {
+        self.entrance_time
+            .observe(time - self.clients[&entity].arrival);
+    }
+
+    fn priority(&self, entity: EntityId) -> u32 {
+        self.config
+            .classes
+            .get(self.clients[&entity].class)
+            .priority
+    }
+
+    fn observe_waiting_time(&mut self, entity: EntityId, waiting_time: SimulationTime) {
+        self.worker_waiting_time.observe(waiting_time);
+        self.worker_waiting_service.observe(waiting_time);
+
+        let class = self.clients[&entity].class as usize;
+        self.class_waiting_time[class].observe(waiting_time);
+    }
+
+    fn client_mut(&mut self, entity: EntityId) -> &mut Client {
+        self.clients
+            .get_mut(&entity)
+            .expect("Event of the client who has already left")
+    }
+
+    fn leave_table(&mut self, entity: EntityId, time: SimulationTime) {
+        let seating = self.client_mut(entity).seating.clone();
+        self.hall.release(&seating);
+        self.seat_from_entrance(time);
+    }
+
+    fn finish_client(&mut self, entity: EntityId, time: SimulationTime, reason: DepartureReason) {
+        let mut client = self.clients.remove(&entity).unwrap();
+        client.departure = Some(time);
+        client.departure_reason = Some(reason);
+
+        if reason == DepartureReason::Served {
+            self.sojourn_service.observe(time - client.arrival);
         }
 
-        let busy_tables = self.config.tables - self.available_tables;
+        if self.record_clients {
+            self.finished_clients.push(client);
+        }
+    }
 
-        self.average_busy_tables.0 += busy_tables as f32;
-        self.average_busy_tables.1 += 1;
+    fn wait_for_worker(
+        &mut self,
+        entity: EntityId,
+        time: SimulationTime,
+        leave_time: SimulationTime,
+        is_first_time: bool,
+    ) {
+        log::trace!("Client is waiting for worker");
 
-        self.average_free_workers.0 += self.available_workers as f32;
-        self.average_free_workers.1 += 1;
+        self.pending_tasks.push_back(Task {
+            entity,
+            kind: TaskKind::TakeOrder,
+            created: time,
+            leave_time: Some(leave_time),
+            is_first_time,
+        });
+        self.calendar
+            .schedule(leave_time, Event::new(entity, EventKind::PatienceExpired));
 
-        self.events = new_events;
+        self.dispatch_workers(time);
     }
 
-    fn partial_result(&mut self) -> Results {
-        result_of(self)
+    fn add_task(&mut self, entity: EntityId, kind: TaskKind, time: SimulationTime) {
+        self.pending_tasks.push_back(Task {
+            entity,
+            kind,
+            created: time,
+            leave_time: None,
+            is_first_time: false,
+        });
+
+        self.dispatch_workers(time);
     }
 
-    fn generate_new_events(&mut self, tick: u32) {
-        let is_client_arrived = thread_rng().gen_bool(self.config.client_ratio);
-        if is_client_arrived {
-            self.events.push_back(Event::Enter(tick));
+    fn release_worker(&mut self, kind: TaskKind) {
+        log::trace!("Worker becomes free");
+        self.busy_workers[kind as usize] -= 1;
+
+        if self.leaving_workers > 0 {
+            log::trace!("Worker leaves at the end of the shift");
+            self.leaving_workers -= 1;
+        } else {
+            self.available_workers += 1;
+        }
+        assert!(self.available_workers <= self.on_duty);
+    }
+
+    /// Schedules holding of tables, arrivals of the groups who come and the no-show timeouts
+    fn schedule_reservations(&mut self) {
+        let book = &self.config.reservations;
+
+        for (index, reservation) in book.entries.iter().enumerate() {
+            let hold_time = (reservation.time - book.hold_before).max(0.0);
+            self.calendar.schedule(
+                hold_time,
+                Event::of_cafe(EventKind::ReservationHold, index as u32),
+            );
+            self.calendar.schedule(
+                reservation.time + book.no_show_timeout,
+                Event::of_cafe(EventKind::ReservationExpired, index as u32),
+            );
+
+            if self.rng.gen_bool(book.show_probability) {
+                let entity = self.next_entity;
+                self.next_entity += 1;
+
+                let arrival_time =
+                    (reservation.time + book.lateness.sample(&mut self.rng)).max(0.0);
+                self.reservation_of.insert(entity, index);
+                self.calendar.schedule(
+                    arrival_time,
+                    Event::new(entity, EventKind::ReservationArrival),
+                );
+            }
+        }
+    }
+
+    fn schedule_shifts(&mut self) {
+        for (index, change) in self.config.staffing.iter().enumerate() {
+            self.calendar.schedule(
+                change.time,
+                Event::of_cafe(EventKind::ShiftChange, index as u32),
+            );
+        }
+    }
+
+    fn change_shift(&mut self, workers: u32, time: SimulationTime) {
+        log::trace!("Shift change: {} -> {workers} workers", self.on_duty);
+
+        if workers >= self.on_duty {
+            let mut arrived = workers - self.on_duty;
+
+            // workers about to leave stay instead of being replaced
+            let staying = arrived.min(self.leaving_workers);
+            self.leaving_workers -= staying;
+            arrived -= staying;
+
+            self.available_workers += arrived;
+        } else {
+            let leaving = self.on_duty - workers;
+            let idle = leaving.min(self.available_workers);
+
+            self.available_workers -= idle;
+            self.leaving_workers += leaving - idle;
+        }
+
+        self.on_duty = workers;
+        self.dispatch_workers(time);
+    }
+
+    /// Picks a task of the highest priority class according to the dispatch policy
+    fn next_task_position(&self) -> Option<usize> {
+        let top = self
+            .pending_tasks
+            .iter()
+            .map(|task| self.priority(task.entity))
+            .max()?;
+        let mut candidates = self
+            .pending_tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| self.priority(task.entity) == top);
+
+        match self.config.dispatch_policy {
+            DispatchPolicy::Fifo => candidates.next(),
+            DispatchPolicy::OldestClient => candidates.min_by(|(_, a), (_, b)| {
+                self.clients[&a.entity]
+                    .arrival
+                    .total_cmp(&self.clients[&b.entity].arrival)
+            }),
+            DispatchPolicy::PaymentsFirst => candidates
+                .clone()
+                .find(|(_, task)| task.kind == TaskKind::TakePayment)
+                .or_else(|| candidates.next()),
+        }
+        .map(|(position, _)| position)
+    }
+
+    fn dispatch_workers(&mut self, time: SimulationTime) {
+        while self.available_workers > 0 {
+            let Some(task) = self
+                .next_task_position()
+                .and_then(|position| self.pending_tasks.remove(position))
+            else {
+                break;
+            };
+
+            self.available_workers -= 1;
+            self.busy_workers[task.kind as usize] += 1;
+
+            let (duration, done) = match task.kind {
+                TaskKind::TakeOrder => {
+                    log::trace!("Client communicates with worker");
+                    self.observe_waiting_time(task.entity, time - task.created);
+
+                    let class = self.clients[&task.entity].class;
+                    let dancing_time = self
+                        .config
+                        .classes
+                        .get(class)
+                        .dancing_time
+                        .as_ref()
+                        .unwrap_or(&self.config.dancing_time)
+                        .sample(&mut self.rng);
+
+                    (dancing_time, EventKind::OrderTaken)
+                }
+                TaskKind::DeliverFood => {
+                    log::trace!("Worker delivers food");
+                    (
+                        self.config.delivery_time.sample(&mut self.rng),
+                        EventKind::FoodDelivered,
+                    )
+                }
+                TaskKind::TakePayment => {
+                    log::trace!("Worker takes payment and clears the table");
+                    (
+                        self.config.payment_time.sample(&mut self.rng),
+                        EventKind::PaymentTaken,
+                    )
+                }
+            };
+
+            self.calendar
+                .schedule(duration + time, Event::new(task.entity, done));
+        }
+    }
+
+    /// Passes the dishes of the order to the kitchen, returns their price
+    fn place_order(&mut self, entity: EntityId, time: SimulationTime) -> f64 {
+        let priority = self.priority(entity);
+
+        if self.config.menu.is_empty() {
+            let production_time = self.config.production_time.sample(&mut self.rng);
+            self.kitchen.place(Order {
+                entity,
+                dish: None,
+                station: 0,
+                placed: time,
+                production_time,
+                priority,
+            });
+            self.pending_dishes.insert(entity, 1);
+
+            return 0.0;
+        }
+
+        let dishes = self.config.menu.dishes_count(&mut self.rng);
+        let mut price = 0.0;
+        for _ in 0..dishes {
+            let index = self.config.menu.pick(&mut self.rng);
+            let dish = &self.config.menu.dishes[index];
+            price += dish.price;
+
+            self.kitchen.place(Order {
+                entity,
+                dish: Some(index),
+                station: self.dish_stations[index],
+                placed: time,
+                production_time: dish.production_time.sample(&mut self.rng),
+                priority,
+            });
+        }
+        self.pending_dishes.insert(entity, dishes);
+
+        price
+    }
+
+    fn start_cooking(&mut self, time: SimulationTime) {
+        while let Some(order) = self.kitchen.start_next() {
+            log::trace!(
+                "Kitchen starts the order of client #{} placed at {}",
+                order.entity,
+                order.placed
+            );
+
+            self.order_time.observe(order.production_time);
+
+            let ticket = self.next_ticket;
+            self.next_ticket += 1;
+            self.calendar.schedule(
+                order.production_time + time,
+                Event::with_payload(order.entity, EventKind::DishReady, ticket),
+            );
+            self.cooking.insert(ticket, order);
+        }
+    }
+
+    /// Schedules the next batch of arrivals, one event for every group
+    fn schedule_arrivals(&mut self, after: Option<SimulationTime>) {
+        let Some(time) = self.arrivals.next(after, &mut self.rng) else {
+            return;
+        };
+
+        self.arrivals_in_batch = self.arrivals.batch_size(&mut self.rng);
+        for _ in 0..self.arrivals_in_batch {
+            let entity = self.next_entity;
+            self.next_entity += 1;
+
+            self.calendar
+                .schedule(time, Event::new(entity, EventKind::Arrival));
         }
     }
 }
 
-fn result_of(sim: &Simulation) -> Results {
-    let average_worker_waiting_time =
-        sim.average_worker_waiting_time.0 / sim.average_worker_waiting_time.1 as f32;
+/// Sets the responses of the run on `results`, which may select only some of them
+fn result_of(sim: &Cafe, mut results: Results) -> Results {
+    results.set_tally("average_worker_waiting_time", &sim.worker_waiting_time);
+    results.set_tally("average_order_time", &sim.order_time);
+    results.set_tally("average_consumption_time", &sim.consumption_time);
+    results.set_tally("average_entrance_time", &sim.entrance_time);
+    results.set_time_weighted("average_busy_tables", &sim.busy_tables);
+    results.set_time_weighted("average_free_workers", &sim.free_workers);
+    results.set_time_weighted("average_order_queue", &sim.order_queue);
+    results.set_time_weighted("average_entrance_queue", &sim.entrance_queue);
+
+    results.set(
+        "seat_utilisation",
+        sim.busy_seats.mean() / sim.hall.total_seats() as f32,
+    );
+
+    let average_scheduled_workers = sim.scheduled_workers.mean();
+    results.set("average_scheduled_workers", average_scheduled_workers);
 
-    let average_order_time = sim.average_order_time.0 / sim.average_order_time.1 as f32;
+    // utilisation is relative to the staff actually on duty, none without staff
+    let [order_utilisation, delivery_utilisation, payment_utilisation] =
+        sim.task_workers.each_ref().map(|busy| {
+            (average_scheduled_workers > 0.0).then(|| busy.mean() / average_scheduled_workers)
+        });
+    results.set("order_utilisation", order_utilisation);
+    results.set("delivery_utilisation", delivery_utilisation);
+    results.set("payment_utilisation", payment_utilisation);
+    results.set(
+        "worker_utilisation",
+        [order_utilisation, delivery_utilisation, payment_utilisation]
+            .into_iter()
+            .sum::<Option<f32>>(),
+    );
+    results.set("kitchen_utilisation", sim.kitchen_utilisation.mean());
 
-    let average_busy_tables = sim.average_busy_tables.0 / sim.average_busy_tables.1 as f32;
+    results.set("dispatched_clients", sim.dispatched_clients_count as f32);
+    results.set("not_dispatched_clients", sim.not_dispatched_clients as f32);
+    results.set(
+        "immediately_left_clients_count",
+        sim.immediately_left_clients_count as f32,
+    );
+    results.set(
+        "entrance_reneged_clients",
+        sim.entrance_reneged_clients as f32,
+    );
 
-    let average_free_workers = sim.average_free_workers.0 / sim.average_free_workers.1 as f32;
+    let economics = &sim.config.economics;
+    let lost_clients = sim.immediately_left_clients_count
+        + sim.not_dispatched_clients
+        + sim.entrance_reneged_clients;
+    let staff_cost = sim.scheduled_workers.area() * economics.worker_hourly_cost;
+    let table_cost =
+        sim.hall.tables().len() as f64 * sim.busy_tables.duration() * economics.table_hourly_cost;
+    let costs = (staff_cost + table_cost) / economics.time_units_per_hour
+        + lost_clients as f64 * economics.lost_client_penalty;
+    results.set("revenue", sim.revenue);
+    results.set("costs", costs as f32);
+    results.set("profit", sim.revenue - costs as f32);
 
-    let average_consumption_time =
-        sim.average_consumption_time.0 / sim.average_consumption_time.1 as f32;
+    for class in ClientClass::ALL {
+        let name = class.name();
+        let class = class as usize;
+        if !results.wants(&format!("{name}.")) {
+            continue;
+        }
+
+        results.set(
+            &format!("{name}.average_worker_waiting_time"),
+            sim.class_waiting_time[class].mean(),
+        );
+        let arrived = sim.class_arrived[class];
+        results.set(
+            &format!("{name}.balking_rate"),
+            (arrived > 0).then(|| sim.class_balked[class] as f32 / arrived as f32),
+        );
+        results.set(
+            &format!("{name}.served_clients"),
+            sim.class_served[class] as f32,
+        );
+    }
 
-    Results {
-        average_consumption_time,
-        average_worker_waiting_time,
-        average_order_time,
-        average_busy_tables,
-        average_free_workers,
-        dispatched_clients: sim.dispatched_clients_count as f32,
-        not_dispatched_clients: sim.not_dispatched_clients as f32,
-        immediately_left_clients_count: sim.immediately_left_clients_count as f32,
+    let dishes = if results.wants("dish_") {
+        sim.dish_cooked.len()
+    } else {
+        0
+    };
+    for (index, (cooked, lead_time)) in sim
+        .dish_cooked
+        .iter()
+        .zip(sim.dish_lead_time.iter())
+        .enumerate()
+        .take(dishes)
+    {
+        results.set(&format!("dish_{index}.throughput"), *cooked as f32);
+        results.set(&format!("dish_{index}.lead_time"), lead_time.mean());
     }
+
+    results.set_time_shares("busy_tables", &sim.busy_tables);
+
+    results.set_service_level("worker_waiting_time", &sim.worker_waiting_service);
+    results.set_service_level("food_waiting_time", &sim.food_waiting_service);
+    results.set_service_level("sojourn_time", &sim.sojourn_service);
+
+    results
 }
@@ -0,0 +1,42 @@
+use crate::{EntityId, SimulationTime};
+
+/// From `time` on `workers` are on duty, a break is a step down and back up
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct StaffChange {
+    pub time: SimulationTime,
+    pub workers: u32,
+}
+
+/// Which pending task a free worker picks next
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DispatchPolicy {
+    #[default]
+    Fifo,
+    /// task of the client who arrived first
+    OldestClient,
+    /// payments are taken before any other task, the rest is FIFO
+    PaymentsFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    TakeOrder,
+    DeliverFood,
+    TakePayment,
+}
+
+impl TaskKind {
+    pub const COUNT: usize = 3;
+}
+
+/// Work waiting for a free worker
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub entity: EntityId,
+    pub kind: TaskKind,
+    pub created: SimulationTime,
+    /// the client stops waiting for the order to be taken at this time
+    pub leave_time: Option<SimulationTime>,
+    pub is_first_time: bool,
+}
@@ -1,31 +1,32 @@
 #![allow(unused)]
-use crate::{chart::Linear, EstimationConfig, Log, Results, Simulation};
+use crate::{chart::Linear, rng, EnsembleLog, EstimationConfig, Responses, Results, Simulation};
 
 const AMOUNT_OF_RUNS: usize = 10;
 
 pub fn task32(config: EstimationConfig) {
     let simulation_config = config.simulation;
-    let mut total_logs = Log::empty();
+    let mut total_logs = EnsembleLog::empty();
     let gap_size = 180;
+    let seed = rng::resolve_seed(simulation_config.seed);
 
     for i in 0..config.experiment.total {
-        let mut simulation = Simulation::with_config(simulation_config.clone());
+        let mut simulation = Simulation::with_stream(simulation_config.clone(), seed, i as u64);
         let (_result, run_log) = simulation.run();
-        total_logs.add_mut(run_log);
+        total_logs.add(&run_log);
     }
 
-    total_logs.norm_mut(config.experiment.total);
+    let busy_tables = total_logs.column("average_busy_tables").unwrap_or_default();
 
     Linear::from_data(
         "Plot name",
-        total_logs.iter()
+        (0..busy_tables.len())
             .skip(180)
-            .map(|(tick, _)| tick as f32).collect(),
+            .map(|tick| tick as f32).collect(),
 
-        total_logs
+        busy_tables
             .iter()
             .skip(180)
-            .map(|(_, entry)| entry.average_busy_tables)
+            .map(|stat| stat.mean().unwrap_or_default())
             .collect(),
     )
     .use_approximation(false)
@@ -0,0 +1,625 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    fs::File,
+    io::{BufWriter, ErrorKind, Write},
+};
+
+use anyhow::Context;
+use serde::de::{
+    value::{Error as ValueError, StrDeserializer},
+    IntoDeserializer,
+};
+
+use crate::{EntityId, Event, EventKind, SimulationTime};
+
+/// Levels of the cafe resources at one moment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Levels {
+    pub busy_tables: u32,
+    pub free_workers: u32,
+    /// workers busy with a task, some of them may leave at the end of their shift
+    pub busy_workers: u32,
+    /// tasks waiting for a free worker
+    pub pending_tasks: usize,
+    /// dishes waiting for a cook
+    pub order_queue: usize,
+    pub entrance_queue: usize,
+}
+
+impl Levels {
+    const NAMES: [&'static str; 6] = [
+        "busy_tables",
+        "free_workers",
+        "busy_workers",
+        "pending_tasks",
+        "order_queue",
+        "entrance_queue",
+    ];
+
+    /// Every level with its name
+    pub fn named(&self) -> impl Iterator<Item = (&'static str, usize)> {
+        Self::NAMES.into_iter().zip(self.values())
+    }
+
+    fn from_values(values: &[usize]) -> Self {
+        Self {
+            busy_tables: values[0] as u32,
+            free_workers: values[1] as u32,
+            busy_workers: values[2] as u32,
+            pending_tasks: values[3],
+            order_queue: values[4],
+            entrance_queue: values[5],
+        }
+    }
+
+    fn values(&self) -> [usize; 6] {
+        [
+            self.busy_tables as usize,
+            self.free_workers as usize,
+            self.busy_workers as usize,
+            self.pending_tasks,
+            self.order_queue,
+            self.entrance_queue,
+        ]
+    }
+}
+
+/// One handled event with the resource levels around it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceEvent {
+    pub time: SimulationTime,
+    pub entity: Option<EntityId>,
+    pub kind: EventKind,
+    pub payload: Option<u32>,
+    pub before: Levels,
+    pub after: Levels,
+}
+
+impl TraceEvent {
+    /// The handled event without its time
+    pub fn event(&self) -> Event {
+        Event {
+            entity: self.entity,
+            kind: self.kind,
+            payload: self.payload,
+        }
+    }
+}
+
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>10.3} {:<40}", self.time, self.event().to_string())?;
+
+        for ((name, before), after) in Levels::NAMES
+            .iter()
+            .zip(self.before.values())
+            .zip(self.after.values())
+        {
+            if before == after {
+                write!(f, " {name}={after}")?;
+            } else {
+                write!(f, " {name}={before}->{after}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceFormat {
+    /// human-readable lines
+    #[default]
+    Console,
+    JsonLines,
+    Csv,
+}
+
+/// Which events get to the trace, an empty list lets everything through
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TraceFilter {
+    #[serde(default)]
+    pub entities: Vec<EntityId>,
+    #[serde(default)]
+    pub kinds: Vec<EventKind>,
+    pub from: Option<SimulationTime>,
+    pub until: Option<SimulationTime>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, event: &TraceEvent) -> bool {
+        (self.entities.is_empty() || event.entity.is_some_and(|e| self.entities.contains(&e)))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && !self.from.is_some_and(|from| event.time < from)
+            && !self.until.is_some_and(|until| event.time > until)
+    }
+}
+
+/// Trace mode, disabled by default
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TraceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub format: TraceFormat,
+    /// file to write to, the standard output if not set
+    pub output: Option<String>,
+    #[serde(default)]
+    pub filter: TraceFilter,
+}
+
+impl TraceConfig {
+    /// Applies the trace options of the command line:
+    /// `--trace`, `--trace-format <console|json_lines|csv>`, `--trace-output <path>`,
+    /// `--trace-entity <id>`, `--trace-kind <event>` (both repeatable),
+    /// `--trace-from <time>`, `--trace-until <time>`.
+    /// Any of them enables the trace.
+    pub fn apply_args(&mut self, args: &[String]) -> anyhow::Result<()> {
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--trace") {
+                continue;
+            }
+            self.enabled = true;
+
+            if arg == "--trace" {
+                continue;
+            }
+            let value = args
+                .next()
+                .with_context(|| format!("Missing value of {arg}"))?;
+
+            match arg.as_str() {
+                "--trace-format" => self.format = parse_name(value)?,
+                "--trace-output" => self.output = Some(value.clone()),
+                "--trace-entity" => self.filter.entities.push(value.parse()?),
+                "--trace-kind" => self.filter.kinds.push(parse_name(value)?),
+                "--trace-from" => self.filter.from = Some(value.parse()?),
+                "--trace-until" => self.filter.until = Some(value.parse()?),
+                _ => anyhow::bail!("Unknown option {arg}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a trace written by the CSV or the JSON Lines sink, the values are looked up by their keys
+pub fn load(path: &str) -> anyhow::Result<Vec<TraceEvent>> {
+    let raw =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read trace {path}"))?;
+    let mut lines = raw
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+
+    let is_json = lines
+        .peek()
+        .is_some_and(|line| line.trim_start().starts_with('{'));
+    if is_json {
+        return lines
+            .map(|line| {
+                let fields =
+                    json_fields(line).with_context(|| format!("Malformed trace line: {line}"))?;
+                event_of(&fields, |name, suffix| format!("{suffix}.{name}"))
+            })
+            .collect();
+    }
+
+    let Some(header) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header = header.split(',').map(str::trim).collect::<Vec<_>>();
+    lines
+        .map(|line| {
+            let values = line.split(',').map(str::trim).collect::<Vec<_>>();
+            anyhow::ensure!(values.len() == header.len(), "Malformed trace line: {line}");
+
+            let fields = header
+                .iter()
+                .zip(values)
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>();
+            event_of(&fields, |name, suffix| format!("{name}_{suffix}"))
+        })
+        .collect()
+}
+
+/// Builds the event from its values by key, `level_key` names a level before or after the event
+fn event_of(
+    fields: &HashMap<String, String>,
+    level_key: impl Fn(&str, &str) -> String,
+) -> anyhow::Result<TraceEvent> {
+    let field = |key: &str| {
+        fields
+            .get(key)
+            .map(String::as_str)
+            .with_context(|| format!("Missing {key} in the trace"))
+    };
+    let levels = |suffix: &str| -> anyhow::Result<Levels> {
+        let values = Levels::NAMES
+            .iter()
+            .map(|name| -> anyhow::Result<usize> { Ok(field(&level_key(name, suffix))?.parse()?) })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Levels::from_values(&values))
+    };
+
+    Ok(TraceEvent {
+        time: field("time")?.parse()?,
+        entity: parse_optional(field("entity")?)?,
+        kind: parse_name(field("kind")?)?,
+        payload: parse_optional(field("payload")?)?,
+        before: levels("before")?,
+        after: levels("after")?,
+    })
+}
+
+/// Values of a JSON object by key, nested keys are joined with a dot like `before.busy_tables`.
+/// Covers what the JSON Lines sink writes: objects, strings, numbers and `null`.
+fn json_fields(line: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut fields = HashMap::new();
+    let rest = json_object(line, "", &mut fields)?;
+    anyhow::ensure!(rest.trim().is_empty(), "Unexpected {rest} after the object");
+
+    Ok(fields)
+}
+
+/// Reads the object at the start of `input`, returns what follows it
+fn json_object<'a>(
+    input: &'a str,
+    prefix: &str,
+    fields: &mut HashMap<String, String>,
+) -> anyhow::Result<&'a str> {
+    let mut rest = input
+        .trim_start()
+        .strip_prefix('{')
+        .context("Expected an object")?;
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok(after);
+        }
+
+        let (key, after) = json_string(rest)?;
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        rest = after
+            .trim_start()
+            .strip_prefix(':')
+            .context("Expected a colon")?
+            .trim_start();
+
+        rest = if rest.starts_with('{') {
+            json_object(rest, &key, fields)?
+        } else if rest.starts_with('"') {
+            let (value, after) = json_string(rest)?;
+            fields.insert(key, value);
+            after
+        } else {
+            let end = rest.find([',', '}']).context("Unterminated object")?;
+            fields.insert(key, rest[..end].trim().to_string());
+            &rest[end..]
+        };
+
+        rest = rest.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+/// Reads the string at the start of `input`, returns it unescaped and what follows it
+fn json_string(input: &str) -> anyhow::Result<(String, &str)> {
+    let body = input.strip_prefix('"').context("Expected a string")?;
+    let mut chars = body.char_indices();
+    let mut value = String::new();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &body[index + 1..])),
+            '\\' => {
+                let (_, escaped) = chars.next().context("Unterminated string")?;
+                value.push(match escaped {
+                    '"' | '\\' | '/' => escaped,
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let mut code = json_code_unit(&mut chars)?;
+                        // a character outside the basic plane is a pair of surrogates
+                        if (0xD800..0xDC00).contains(&code) {
+                            let low = match (chars.next(), chars.next()) {
+                                (Some((_, '\\')), Some((_, 'u'))) => json_code_unit(&mut chars)?,
+                                _ => 0,
+                            };
+                            anyhow::ensure!(
+                                (0xDC00..0xE000).contains(&low),
+                                "Unpaired surrogate in a string"
+                            );
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        char::from_u32(code).context("Bad escape in a string")?
+                    }
+                    other => anyhow::bail!("Unknown escape \\{other} in a string"),
+                });
+            }
+            c => value.push(c),
+        }
+    }
+
+    anyhow::bail!("Unterminated string")
+}
+
+/// The four hex digits of a `\u` escape
+fn json_code_unit(chars: &mut std::str::CharIndices) -> anyhow::Result<u32> {
+    let hex = (0..4)
+        .map(|_| chars.next().map(|(_, c)| c))
+        .collect::<Option<String>>()
+        .context("Unterminated escape in a string")?;
+
+    Ok(u32::from_str_radix(&hex, 16)?)
+}
+
+/// The string in quotes with the characters JSON does not allow escaped
+fn json_quoted(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// Empty or `null` is `None`
+fn parse_optional(value: &str) -> anyhow::Result<Option<u32>> {
+    match value {
+        "" | "null" => Ok(None),
+        value => Ok(Some(value.parse()?)),
+    }
+}
+
+/// Parses a snake_case name of an enum variant
+fn parse_name<'de, T: serde::Deserialize<'de>>(value: &'de str) -> anyhow::Result<T> {
+    let deserializer: StrDeserializer<'de, ValueError> = value.into_deserializer();
+    T::deserialize(deserializer).with_context(|| format!("Unknown value {value}"))
+}
+
+/// Destination of the trace events
+pub trait TraceSink {
+    fn record(&mut self, event: &TraceEvent) -> std::io::Result<()>;
+}
+
+pub struct ConsoleSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceSink for ConsoleSink<W> {
+    fn record(&mut self, event: &TraceEvent) -> std::io::Result<()> {
+        writeln!(self.writer, "{event}")
+    }
+}
+
+/// One JSON object per line
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceSink for JsonLinesSink<W> {
+    fn record(&mut self, event: &TraceEvent) -> std::io::Result<()> {
+        let levels = |levels: &Levels| {
+            Levels::NAMES
+                .iter()
+                .zip(levels.values())
+                .map(|(name, value)| format!("\"{name}\":{value}"))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let fields = [
+            format!("\"time\":{}", event.time),
+            format!("\"entity\":{}", optional(event.entity, "null")),
+            format!("\"kind\":{}", json_quoted(event.kind.name())),
+            format!("\"payload\":{}", optional(event.payload, "null")),
+            format!("\"before\":{{{}}}", levels(&event.before)),
+            format!("\"after\":{{{}}}", levels(&event.after)),
+        ];
+        writeln!(self.writer, "{{{}}}", fields.join(","))
+    }
+}
+
+fn optional(value: Option<u32>, none: &str) -> String {
+    value.map_or_else(|| none.to_string(), |value| value.to_string())
+}
+
+/// A header, then one row per event with the levels before and after it
+pub struct CsvSink<W: Write> {
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write> TraceSink for CsvSink<W> {
+    fn record(&mut self, event: &TraceEvent) -> std::io::Result<()> {
+        if !self.header_written {
+            let mut header = vec![
+                "time".to_string(),
+                "entity".into(),
+                "kind".into(),
+                "payload".into(),
+            ];
+            for suffix in ["before", "after"] {
+                header.extend(Levels::NAMES.iter().map(|name| format!("{name}_{suffix}")));
+            }
+            writeln!(self.writer, "{}", header.join(","))?;
+            self.header_written = true;
+        }
+
+        let mut row = vec![
+            event.time.to_string(),
+            optional(event.entity, ""),
+            event.kind.name().to_string(),
+            optional(event.payload, ""),
+        ];
+        for levels in [&event.before, &event.after] {
+            row.extend(levels.values().iter().map(|value| value.to_string()));
+        }
+        writeln!(self.writer, "{}", row.join(","))
+    }
+}
+
+/// Passes the events which match the filter to the sink
+pub struct Tracer {
+    filter: TraceFilter,
+    sink: Box<dyn TraceSink>,
+    /// the reader of the trace has gone, e.g. `saimod --trace | head`
+    closed: bool,
+}
+
+impl Tracer {
+    /// `None` if the trace is disabled
+    pub fn from_config(config: &TraceConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let writer: Box<dyn Write> = match &config.output {
+            Some(path) => Box::new(BufWriter::new(
+                File::create(path).expect("Failed to create trace file"),
+            )),
+            None => Box::new(std::io::stdout()),
+        };
+
+        let sink: Box<dyn TraceSink> = match config.format {
+            TraceFormat::Console => Box::new(ConsoleSink { writer }),
+            TraceFormat::JsonLines => Box::new(JsonLinesSink { writer }),
+            TraceFormat::Csv => Box::new(CsvSink {
+                writer,
+                header_written: false,
+            }),
+        };
+
+        Some(Self::new(config.filter.clone(), sink))
+    }
+
+    pub fn new(filter: TraceFilter, sink: Box<dyn TraceSink>) -> Self {
+        Self {
+            filter,
+            sink,
+            closed: false,
+        }
+    }
+
+    /// A broken pipe ends the trace, the run goes on
+    pub fn record(&mut self, event: TraceEvent) {
+        if self.closed || !self.filter.matches(&event) {
+            return;
+        }
+
+        match self.sink.record(&event) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => self.closed = true,
+            Err(e) => panic!("Failed to write trace: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<TraceEvent> {
+        let levels = |offset: usize| {
+            Levels::from_values(&[3, 2, 1, 4, 5, 0].map(|value: usize| value + offset))
+        };
+
+        vec![
+            TraceEvent {
+                time: 0.0,
+                entity: Some(1),
+                kind: EventKind::Arrival,
+                payload: None,
+                before: levels(0),
+                after: levels(1),
+            },
+            TraceEvent {
+                time: 12.345678901234,
+                entity: Some(17),
+                kind: EventKind::DishReady,
+                payload: Some(42),
+                before: levels(1),
+                after: levels(1),
+            },
+            TraceEvent {
+                time: 300.0,
+                entity: None,
+                kind: EventKind::ShiftChange,
+                payload: Some(2),
+                before: levels(2),
+                after: levels(0),
+            },
+        ]
+    }
+
+    fn record_all(mut sink: impl TraceSink) {
+        for event in events().iter() {
+            sink.record(event).unwrap();
+        }
+    }
+
+    /// Loads the trace from a file named `name` with the `written` content
+    fn load_written(name: &str, written: &[u8]) -> Vec<TraceEvent> {
+        let path = std::env::temp_dir().join(format!("saimod_trace_{name}"));
+        std::fs::write(&path, written).unwrap();
+        load(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let mut written = Vec::new();
+        record_all(JsonLinesSink {
+            writer: &mut written,
+        });
+
+        assert_eq!(load_written("json_lines", &written), events());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let mut written = Vec::new();
+        record_all(CsvSink {
+            writer: &mut written,
+            header_written: false,
+        });
+
+        assert_eq!(load_written("csv", &written), events());
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        let input = format!("{}, rest", json_quoted("a \"quoted\" \\ path\n\u{1}"));
+        let (value, rest) = json_string(&input).unwrap();
+        assert_eq!(value, "a \"quoted\" \\ path\n\u{1}");
+        assert_eq!(rest, ", rest");
+
+        let (value, _) = json_string(r#""café 🍰 \/""#).unwrap();
+        assert_eq!(value, "café 🍰 /");
+
+        assert!(json_string(r#""\ud83c""#).is_err());
+        assert!(json_string(r#""unterminated"#).is_err());
+    }
+}